@@ -1,10 +1,18 @@
 use crate::{
     redis::manager::RedisManager,
-    types::api::{MessageFromApi, MessageToApi},
+    types::{
+        admin::AdminAction,
+        api::{MessageFromApi, MessageToApi},
+        db::{DbQuery, DbRequest, DbResponse, HistoryFilter},
+        market::{MarketKind, MarketMetadata, Resolution, ScalarRange, TradingConfig},
+        market_data::CandleInterval,
+        order::OptionType,
+        risk::{RiskLimits, RiskScope},
+    },
 };
 use actix::AsyncContext;
 use actix_cors::Cors;
-use actix_web::{App, HttpResponse, HttpServer, Responder, web};
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, Responder, http::header, web};
 use actix_web_actors::ws;
 use futures_util::StreamExt;
 use serde::Deserialize;
@@ -15,10 +23,16 @@ use uuid::Uuid;
 #[derive(Clone)]
 struct AppState {
     redis: RedisManager,
+    // Bearer token the operator endpoints require; unset, they are closed
+    admin_token: Option<String>,
 }
 
-pub async fn run_api_server(redis: RedisManager, addr: &str) -> std::io::Result<()> {
-    let state = Arc::new(AppState { redis });
+pub async fn run_api_server(
+    redis: RedisManager,
+    addr: &str,
+    admin_token: Option<String>,
+) -> std::io::Result<()> {
+    let state = Arc::new(AppState { redis, admin_token });
     HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
@@ -32,6 +46,27 @@ pub async fn run_api_server(redis: RedisManager, addr: &str) -> std::io::Result<
             .route("/open_orders", web::post().to(get_open_orders))
             .route("/depth", web::post().to(get_depth))
             .route("/market", web::post().to(create_market))
+            .route("/l3_snapshot", web::post().to(get_l3_snapshot))
+            .route("/candles", web::post().to(get_candles))
+            .route("/ticker", web::get().to(get_tickers))
+            .route("/ticker/{market_id}", web::get().to(get_ticker))
+            .route("/combined_depth", web::post().to(get_combined_depth))
+            .route("/amm", web::post().to(get_amm))
+            .route("/stake", web::post().to(place_stake))
+            .route("/pool", web::post().to(get_pool))
+            .route("/resolve", web::post().to(resolve_market))
+            .route("/risk_limits", web::post().to(set_risk_limits))
+            .route("/admin", web::post().to(apply_admin_action))
+            .route("/audit", web::get().to(get_audit_log))
+            .route("/account", web::post().to(create_account))
+            .route("/deposit", web::post().to(deposit))
+            .route("/withdraw", web::post().to(withdraw))
+            .route("/ledger/{user_id}", web::get().to(get_ledger))
+            .route("/balance", web::get().to(get_balance))
+            .route("/positions", web::get().to(get_positions))
+            .route("/history/orders", web::get().to(get_order_history))
+            .route("/history/trades", web::get().to(get_trade_history))
+            .route("/trades/{market_id}", web::get().to(get_recent_trades))
             .route("/ws", web::get().to(ws_index))
             .route("/events", web::get().to(get_events))
     })
@@ -41,15 +76,15 @@ pub async fn run_api_server(redis: RedisManager, addr: &str) -> std::io::Result<
 }
 
 async fn get_events(state: web::Data<Arc<AppState>>) -> impl Responder {
-    let mut markets = Vec::new();
-    match state.redis.pop_message::<Vec<String>>("markets_list").await {
-        Ok(Some(market_ids)) => {
-            for market_id in market_ids {
-                markets.push(serde_json::json!({ "market_id": market_id }));
-            }
-            HttpResponse::Ok().json(markets)
-        }
-        _ => HttpResponse::Ok().json(Vec::<String>::new()),
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::GetMarkets {
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::Markets { markets, .. }) => HttpResponse::Ok().json(markets),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
     }
 }
 
@@ -104,6 +139,9 @@ async fn place_order(
         Some(MessageToApi::OrderMatched { trade, .. }) => {
             HttpResponse::Ok().json(serde_json::to_value(&trade).unwrap())
         }
+        Some(MessageToApi::OrderRejected { code, message, .. }) => {
+            HttpResponse::BadRequest().json(serde_json::json!({ "code": code, "message": message }))
+        }
         Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
         _ => HttpResponse::InternalServerError().body("No response received"),
     }
@@ -163,6 +201,9 @@ async fn cancel_order(
 struct OpenOrdersRequest {
     user_id: u32,
     market_id: String,
+    // Still accepted from existing clients; the id sent to the engine is
+    // generated server-side
+    #[allow(dead_code)]
     client_id: String,
 }
 
@@ -203,22 +244,20 @@ async fn get_open_orders(
     let response = timeout(Duration::from_secs(5), pubsub.on_message().next()).await;
     match response {
         Ok(Some(msg)) => {
-            if let Ok(payload) = msg.get_payload::<String>() {
-                if let Ok(message) = serde_json::from_str::<MessageToApi>(&payload) {
-                    if matches_client_id(&message, &client_id) {
-                        match message {
-                            MessageToApi::OpenOrders { orders, .. } => {
-                                return HttpResponse::Ok()
-                                    .json(serde_json::to_value(&orders).unwrap());
-                            }
-                            MessageToApi::Error { message, .. } => {
-                                return HttpResponse::BadRequest().body(message);
-                            }
-                            _ => {
-                                return HttpResponse::InternalServerError()
-                                    .body("Unexpected response type");
-                            }
-                        }
+            if let Ok(payload) = msg.get_payload::<String>()
+                && let Ok(message) = serde_json::from_str::<MessageToApi>(&payload)
+                && message.client_id() == Some(client_id.as_str())
+            {
+                match message {
+                    MessageToApi::OpenOrders { orders, .. } => {
+                        return HttpResponse::Ok().json(serde_json::to_value(&orders).unwrap());
+                    }
+                    MessageToApi::Error { message, .. } => {
+                        return HttpResponse::BadRequest().body(message);
+                    }
+                    _ => {
+                        return HttpResponse::InternalServerError()
+                            .body("Unexpected response type");
                     }
                 }
             }
@@ -273,34 +312,33 @@ async fn get_depth(
     let response = timeout(Duration::from_secs(5), pubsub.on_message().next()).await;
     match response {
         Ok(Some(msg)) => {
-            if let Ok(payload) = msg.get_payload::<String>() {
-                if let Ok(message) = serde_json::from_str::<MessageToApi>(&payload) {
-                    if matches_client_id(&message, &req.client_id) {
-                        match message {
-                            MessageToApi::Depth {
-                                market_id,
-                                yes_bids,
-                                yes_asks,
-                                no_bids,
-                                no_asks,
-                                client_id,
-                                ..
-                            } => {
-                                return HttpResponse::Ok().json(
-                                    serde_json::to_value(&(
-                                        market_id, yes_bids, yes_asks, no_bids, no_asks, client_id,
-                                    ))
-                                    .unwrap(),
-                                );
-                            }
-                            MessageToApi::Error { message, .. } => {
-                                return HttpResponse::BadRequest().body(message);
-                            }
-                            _ => {
-                                return HttpResponse::InternalServerError()
-                                    .body("Unexpected response type");
-                            }
-                        }
+            if let Ok(payload) = msg.get_payload::<String>()
+                && let Ok(message) = serde_json::from_str::<MessageToApi>(&payload)
+                && message.client_id() == Some(req.client_id.as_str())
+            {
+                match message {
+                    MessageToApi::Depth {
+                        market_id,
+                        yes_bids,
+                        yes_asks,
+                        no_bids,
+                        no_asks,
+                        client_id,
+                        ..
+                    } => {
+                        return HttpResponse::Ok().json(
+                            serde_json::to_value(&(
+                                market_id, yes_bids, yes_asks, no_bids, no_asks, client_id,
+                            ))
+                            .unwrap(),
+                        );
+                    }
+                    MessageToApi::Error { message, .. } => {
+                        return HttpResponse::BadRequest().body(message);
+                    }
+                    _ => {
+                        return HttpResponse::InternalServerError()
+                            .body("Unexpected response type");
                     }
                 }
             }
@@ -314,6 +352,15 @@ async fn get_depth(
 struct CreateMarketRequest {
     market_id: String,
     question: String,
+    #[serde(default)]
+    kind: MarketKind,
+    #[serde(default)]
+    outcomes: Vec<String>,
+    range: Option<ScalarRange>,
+    #[serde(flatten)]
+    metadata: MarketMetadata,
+    #[serde(flatten)]
+    trading: TradingConfig,
     client_id: String,
 }
 
@@ -324,6 +371,11 @@ async fn create_market(
     let message = MessageFromApi::CreateMarket {
         market_id: req.market_id.clone(),
         question: req.question.clone(),
+        kind: req.kind,
+        outcomes: req.outcomes.clone(),
+        range: req.range,
+        metadata: Box::new(req.metadata.clone()),
+        trading: req.trading.clone(),
         client_id: req.client_id.clone(),
     };
 
@@ -343,18 +395,602 @@ async fn create_market(
     }
 }
 
+#[derive(Deserialize)]
+struct L3SnapshotRequest {
+    market_id: String,
+}
+
+async fn get_l3_snapshot(
+    state: web::Data<Arc<AppState>>,
+    req: web::Json<L3SnapshotRequest>,
+) -> impl Responder {
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::GetL3Snapshot {
+        market_id: req.market_id.clone(),
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::L3Snapshot {
+            market_id, yes, no, ..
+        }) => HttpResponse::Ok().json(serde_json::json!({
+            "market_id": market_id,
+            "yes": yes,
+            "no": no,
+        })),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+#[derive(Deserialize)]
+struct CandlesRequest {
+    market_id: String,
+    option: OptionType,
+    interval: CandleInterval,
+    limit: Option<usize>,
+}
+
+async fn get_candles(
+    state: web::Data<Arc<AppState>>,
+    req: web::Json<CandlesRequest>,
+) -> impl Responder {
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::GetCandles {
+        market_id: req.market_id.clone(),
+        option: req.option,
+        interval: req.interval,
+        limit: req.limit.unwrap_or(500),
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::Candles { candles, .. }) => HttpResponse::Ok().json(candles),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+async fn get_tickers(state: web::Data<Arc<AppState>>) -> impl Responder {
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::GetTicker {
+        market_id: None,
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::Tickers { tickers, .. }) => HttpResponse::Ok().json(tickers),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+async fn get_ticker(
+    state: web::Data<Arc<AppState>>,
+    market_id: web::Path<String>,
+) -> impl Responder {
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::GetTicker {
+        market_id: Some(market_id.into_inner()),
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::Tickers { tickers, .. }) => match tickers.into_iter().next() {
+            Some(ticker) => HttpResponse::Ok().json(ticker),
+            None => HttpResponse::NotFound().body("Market not found"),
+        },
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+#[derive(Deserialize)]
+struct CombinedDepthRequest {
+    market_id: String,
+}
+
+async fn get_combined_depth(
+    state: web::Data<Arc<AppState>>,
+    req: web::Json<CombinedDepthRequest>,
+) -> impl Responder {
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::GetCombinedDepth {
+        market_id: req.market_id.clone(),
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::CombinedDepth { book, .. }) => HttpResponse::Ok().json(book),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+#[derive(Deserialize)]
+struct AmmRequest {
+    market_id: String,
+}
+
+async fn get_amm(state: web::Data<Arc<AppState>>, req: web::Json<AmmRequest>) -> impl Responder {
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::GetAmm {
+        market_id: req.market_id.clone(),
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::Amm { amm, .. }) => HttpResponse::Ok().json(amm),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+#[derive(Deserialize)]
+struct StakeRequest {
+    user_id: u32,
+    market_id: String,
+    option: OptionType,
+    amount: f64,
+}
+
+async fn place_stake(
+    state: web::Data<Arc<AppState>>,
+    req: web::Json<StakeRequest>,
+) -> impl Responder {
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::PlaceStake {
+        user_id: req.user_id,
+        market_id: req.market_id.clone(),
+        option: req.option,
+        amount: req.amount,
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::StakePlaced { pool, .. }) => HttpResponse::Ok().json(pool),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+#[derive(Deserialize)]
+struct PoolRequest {
+    market_id: String,
+}
+
+async fn get_pool(state: web::Data<Arc<AppState>>, req: web::Json<PoolRequest>) -> impl Responder {
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::GetPool {
+        market_id: req.market_id.clone(),
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::Pool { pool, .. }) => HttpResponse::Ok().json(pool),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+#[derive(Deserialize)]
+struct ResolveMarketRequest {
+    market_id: String,
+    resolution: Resolution,
+}
+
+async fn resolve_market(
+    state: web::Data<Arc<AppState>>,
+    http: HttpRequest,
+    req: web::Json<ResolveMarketRequest>,
+) -> impl Responder {
+    if let Err(response) = authorize_admin(&state, &http) {
+        return response;
+    }
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::ResolveMarket {
+        market_id: req.market_id.clone(),
+        resolution: req.resolution.clone(),
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::MarketResolved {
+            market_id,
+            resolution,
+            ..
+        }) => HttpResponse::Ok().json(serde_json::json!({
+            "market_id": market_id,
+            "resolution": resolution,
+        })),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+#[derive(Deserialize)]
+struct RiskLimitsRequest {
+    scope: RiskScope,
+    limits: RiskLimits,
+}
+
+async fn set_risk_limits(
+    state: web::Data<Arc<AppState>>,
+    http: HttpRequest,
+    req: web::Json<RiskLimitsRequest>,
+) -> impl Responder {
+    if let Err(response) = authorize_admin(&state, &http) {
+        return response;
+    }
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::SetRiskLimits {
+        scope: req.scope.clone(),
+        limits: req.limits,
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::RiskLimitsSet { scope, limits, .. }) => {
+            HttpResponse::Ok().json(serde_json::json!({ "scope": scope, "limits": limits }))
+        }
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+#[derive(Deserialize)]
+struct AdminRequest {
+    action: AdminAction,
+    operator: String,
+    reason: String,
+}
+
+// Operator endpoints take `Authorization: Bearer <token>`. Besides the admin
+// commands these are the ones that move or reveal money for any user:
+// resolving markets, crediting deposits and reading ledgers
+fn authorize_admin(state: &AppState, http: &HttpRequest) -> Result<(), HttpResponse> {
+    let Some(expected) = state.admin_token.as_deref() else {
+        return Err(HttpResponse::Forbidden().body("Admin endpoints are disabled"));
+    };
+    let given = http
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // Compare every byte so the time taken doesn't leak the prefix matched
+    let matches = given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    if matches {
+        Ok(())
+    } else {
+        Err(HttpResponse::Unauthorized().body("Invalid admin token"))
+    }
+}
+
+async fn apply_admin_action(
+    state: web::Data<Arc<AppState>>,
+    http: HttpRequest,
+    req: web::Json<AdminRequest>,
+) -> impl Responder {
+    if let Err(response) = authorize_admin(&state, &http) {
+        return response;
+    }
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::Admin {
+        action: req.action.clone(),
+        operator: req.operator.clone(),
+        reason: req.reason.clone(),
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::AdminApplied { entry, .. }) => HttpResponse::Ok().json(entry),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+async fn get_audit_log(state: web::Data<Arc<AppState>>, http: HttpRequest) -> impl Responder {
+    if let Err(response) = authorize_admin(&state, &http) {
+        return response;
+    }
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::GetAuditLog {
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::AuditLog { entries, .. }) => HttpResponse::Ok().json(entries),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+async fn get_ledger(
+    state: web::Data<Arc<AppState>>,
+    http: HttpRequest,
+    user_id: web::Path<u32>,
+) -> impl Responder {
+    if let Err(response) = authorize_admin(&state, &http) {
+        return response;
+    }
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::GetLedger {
+        user_id: user_id.into_inner(),
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::Ledger { audit, .. }) => HttpResponse::Ok().json(audit),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+#[derive(Deserialize)]
+struct UserQuery {
+    user_id: u32,
+}
+
+async fn get_balance(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<UserQuery>,
+) -> impl Responder {
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::GetBalance {
+        user_id: query.user_id,
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::Balance { balance, .. }) => HttpResponse::Ok().json(balance),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+async fn get_positions(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<UserQuery>,
+) -> impl Responder {
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::GetPositions {
+        user_id: query.user_id,
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::Positions { positions, .. }) => HttpResponse::Ok().json(positions),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+async fn get_order_history(
+    state: web::Data<Arc<AppState>>,
+    filter: web::Query<HistoryFilter>,
+) -> impl Responder {
+    match request_db(&state.redis, DbQuery::OrderHistory(filter.into_inner())).await {
+        Some(DbResponse::Orders { page, .. }) => HttpResponse::Ok().json(page),
+        Some(DbResponse::Error { message, .. }) => {
+            HttpResponse::InternalServerError().body(message)
+        }
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+async fn get_trade_history(
+    state: web::Data<Arc<AppState>>,
+    filter: web::Query<HistoryFilter>,
+) -> impl Responder {
+    match request_db(&state.redis, DbQuery::TradeHistory(filter.into_inner())).await {
+        Some(DbResponse::Trades { page, .. }) => HttpResponse::Ok().json(page),
+        Some(DbResponse::Error { message, .. }) => {
+            HttpResponse::InternalServerError().body(message)
+        }
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+#[derive(Deserialize)]
+struct RecentTradesQuery {
+    limit: Option<usize>,
+}
+
+async fn get_recent_trades(
+    state: web::Data<Arc<AppState>>,
+    market_id: web::Path<String>,
+    query: web::Query<RecentTradesQuery>,
+) -> impl Responder {
+    let query = DbQuery::RecentTrades {
+        market_id: market_id.into_inner(),
+        limit: query.limit.unwrap_or(50),
+    };
+    match request_db(&state.redis, query).await {
+        Some(DbResponse::Trades { page, .. }) => HttpResponse::Ok().json(page.items),
+        Some(DbResponse::Error { message, .. }) => {
+            HttpResponse::InternalServerError().body(message)
+        }
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+#[derive(Deserialize)]
+struct AccountRequest {
+    user_id: u32,
+}
+
+async fn create_account(
+    state: web::Data<Arc<AppState>>,
+    req: web::Json<AccountRequest>,
+) -> impl Responder {
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::CreateAccount {
+        user_id: req.user_id,
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::AccountCreated { user_id, .. }) => {
+            HttpResponse::Ok().json(serde_json::json!({ "user_id": user_id }))
+        }
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+#[derive(Deserialize)]
+struct TransferRequest {
+    user_id: u32,
+    amount: f64,
+}
+
+async fn deposit(
+    state: web::Data<Arc<AppState>>,
+    http: HttpRequest,
+    req: web::Json<TransferRequest>,
+) -> impl Responder {
+    if let Err(response) = authorize_admin(&state, &http) {
+        return response;
+    }
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::Deposit {
+        user_id: req.user_id,
+        amount: req.amount,
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::TransferCompleted { transfer, .. }) => HttpResponse::Ok().json(transfer),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+async fn withdraw(
+    state: web::Data<Arc<AppState>>,
+    req: web::Json<TransferRequest>,
+) -> impl Responder {
+    let client_id = Uuid::new_v4().to_string();
+    let message = MessageFromApi::Withdraw {
+        user_id: req.user_id,
+        amount: req.amount,
+        client_id: client_id.clone(),
+    };
+
+    match request_engine(&state.redis, &message, &client_id).await {
+        Some(MessageToApi::TransferCompleted { transfer, .. }) => HttpResponse::Ok().json(transfer),
+        Some(MessageToApi::Error { message, .. }) => HttpResponse::BadRequest().body(message),
+        _ => HttpResponse::InternalServerError().body("No response received"),
+    }
+}
+
+// Helper function to push a request to the engine and wait for the response
+// carrying the same client_id. Subscribes before pushing so a fast reply is
+// never missed, and skips responses meant for other clients.
+async fn request_engine(
+    redis: &RedisManager,
+    message: &MessageFromApi,
+    client_id: &str,
+) -> Option<MessageToApi> {
+    let mut pubsub = match redis.subscribe("responses").await {
+        Ok(pubsub) => pubsub,
+        Err(e) => {
+            tracing::error!("Failed to subscribe to responses: {}", e);
+            return None;
+        }
+    };
+
+    if let Err(e) = redis.push_message("engine_queue", message).await {
+        tracing::error!("Failed to push message to engine_queue: {}", e);
+        return None;
+    }
+
+    let mut messages = pubsub.on_message();
+    let result = timeout(Duration::from_secs(5), async {
+        while let Some(msg) = messages.next().await {
+            if let Ok(payload) = msg.get_payload::<String>()
+                && let Ok(message) = serde_json::from_str::<MessageToApi>(&payload)
+                && message.client_id() == Some(client_id)
+            {
+                return Some(message);
+            }
+        }
+        None
+    })
+    .await;
+
+    match result {
+        Ok(message) => message,
+        Err(_) => {
+            tracing::warn!("Timeout waiting for response for client_id: {}", client_id);
+            None
+        }
+    }
+}
+
+// Same round trip as `request_engine`, against the DB processor's query queue
+async fn request_db(redis: &RedisManager, query: DbQuery) -> Option<DbResponse> {
+    let client_id = Uuid::new_v4().to_string();
+    let mut pubsub = match redis.subscribe("db_responses").await {
+        Ok(pubsub) => pubsub,
+        Err(e) => {
+            tracing::error!("Failed to subscribe to db_responses: {}", e);
+            return None;
+        }
+    };
+
+    let request = DbRequest {
+        query,
+        client_id: client_id.clone(),
+    };
+    if let Err(e) = redis.push_message("db_queries", &request).await {
+        tracing::error!("Failed to push message to db_queries: {}", e);
+        return None;
+    }
+
+    let mut messages = pubsub.on_message();
+    let result = timeout(Duration::from_secs(5), async {
+        while let Some(msg) = messages.next().await {
+            if let Ok(payload) = msg.get_payload::<String>()
+                && let Ok(response) = serde_json::from_str::<DbResponse>(&payload)
+                && response.client_id() == client_id
+            {
+                return Some(response);
+            }
+        }
+        None
+    })
+    .await;
+
+    match result {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!(
+                "Timeout waiting for DB response for client_id: {}",
+                client_id
+            );
+            None
+        }
+    }
+}
+
 // Helper function to wait for a response from the "responses" channel
 async fn wait_for_response(redis: &RedisManager, client_id: &str) -> Option<MessageToApi> {
     let mut pubsub = redis.subscribe("responses").await.unwrap();
     let result = timeout(Duration::from_secs(5), pubsub.on_message().next()).await;
     match result {
         Ok(Some(msg)) => {
-            if let Ok(payload) = msg.get_payload::<String>() {
-                if let Ok(message) = serde_json::from_str::<MessageToApi>(&payload) {
-                    if matches_client_id(&message, client_id) {
-                        return Some(message);
-                    }
-                }
+            if let Ok(payload) = msg.get_payload::<String>()
+                && let Ok(message) = serde_json::from_str::<MessageToApi>(&payload)
+                && message.client_id() == Some(client_id)
+            {
+                return Some(message);
             }
             None
         }
@@ -362,21 +998,12 @@ async fn wait_for_response(redis: &RedisManager, client_id: &str) -> Option<Mess
     }
 }
 
-fn matches_client_id(message: &MessageToApi, client_id: &str) -> bool {
-    match message {
-        MessageToApi::OrderPlaced { client_id: cid, .. } => cid == client_id,
-        MessageToApi::OrderMatched { client_id: cid, .. } => cid == client_id,
-        MessageToApi::OrderCancelled { client_id: cid, .. } => cid == client_id,
-        MessageToApi::OpenOrders { client_id: cid, .. } => cid == client_id,
-        MessageToApi::MarketCreated { client_id: cid, .. } => cid == client_id,
-        MessageToApi::Error { client_id: cid, .. } => cid == client_id,
-        MessageToApi::Depth { client_id: cid, .. } => cid == client_id,
-    }
-}
-
 struct WsActor {
     redis: RedisManager,
     client_id: String,
+    /// Owner of the private stream, if the client gave one; taken on trust,
+    /// see `WsQuery::user_id`.
+    user_id: Option<u32>,
 }
 
 impl actix::Actor for WsActor {
@@ -385,30 +1012,26 @@ impl actix::Actor for WsActor {
     fn started(&mut self, ctx: &mut Self::Context) {
         let redis = self.redis.clone();
         let client_id = self.client_id.clone();
+        let user_id = self.user_id;
         let addr = ctx.address();
 
         // Create a future to handle Redis subscription
         let fut = async move {
             if let Ok(mut pubsub) = redis.subscribe("responses").await {
                 while let Some(msg) = pubsub.on_message().next().await {
-                    if let Ok(payload) = msg.get_payload::<String>() {
-                        if let Ok(message) = serde_json::from_str::<MessageToApi>(&payload) {
-                            // Filter by client_id
-                            let message_client_id = match &message {
-                                MessageToApi::OrderPlaced { client_id, .. } => client_id,
-                                MessageToApi::OrderMatched { client_id, .. } => client_id,
-                                MessageToApi::OrderCancelled { client_id, .. } => client_id,
-                                MessageToApi::OpenOrders { client_id, .. } => client_id,
-                                MessageToApi::Depth { client_id, .. } => client_id,
-                                MessageToApi::MarketCreated { client_id, .. } => client_id,
-                                MessageToApi::Error { client_id, .. } => client_id,
-                            };
-
-                            if message_client_id == &client_id {
-                                if let Ok(json) = serde_json::to_string(&message) {
-                                    let _ = addr.do_send(WsMessage(json));
-                                }
+                    if let Ok(payload) = msg.get_payload::<String>()
+                        && let Ok(message) = serde_json::from_str::<MessageToApi>(&payload)
+                    {
+                        // Balance pushes go to the owner's stream; everything
+                        // else is filtered by client_id
+                        let wanted = match &message {
+                            MessageToApi::BalanceUpdated { balance } => {
+                                Some(balance.user_id) == user_id
                             }
+                            _ => message.client_id() == Some(client_id.as_str()),
+                        };
+                        if wanted && let Ok(json) = serde_json::to_string(&message) {
+                            addr.do_send(WsMessage(json));
                         }
                     }
                 }
@@ -444,22 +1067,32 @@ impl actix::StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsActor {
     }
 }
 
+#[derive(Deserialize, Default)]
+struct WsQuery {
+    client_id: Option<String>,
+    /// Subscribes to this user's balance pushes. Not authenticated: like
+    /// every other user endpoint, the stream trusts the `user_id` it is
+    /// given until the platform has user auth, so anyone who can reach the
+    /// port can follow any account's balance.
+    user_id: Option<u32>,
+}
+
 async fn ws_index(
     req: actix_web::HttpRequest,
     stream: web::Payload,
     state: web::Data<Arc<AppState>>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let client_id = req
-        .query_string()
-        .split("client_id=")
-        .collect::<Vec<&str>>()
-        .get(1)
-        .map(|s| s.to_string())
+    let query = web::Query::<WsQuery>::from_query(req.query_string())
+        .map(|q| q.into_inner())
+        .unwrap_or_default();
+    let client_id = query
+        .client_id
         .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
 
     let ws = WsActor {
         redis: state.redis.clone(),
         client_id,
+        user_id: query.user_id,
     };
 
     ws::start(ws, &req, stream)
@@ -0,0 +1,122 @@
+use crate::{
+    db::{
+        memory::MemoryStore,
+        storage::{Storage, WriteError},
+    },
+    types::{
+        db::{DbWrite, HistoryFilter, LastIds, Page, StoredAccounts},
+        order::{Order, Trade},
+    },
+};
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::Write,
+};
+
+/// Every write appended to a file, one JSON object per line. The file is
+/// replayed into memory on open and queries are answered from there.
+pub struct JsonlStore {
+    file: File,
+    memory: MemoryStore,
+    // A failed append may have left part of a line behind
+    torn: bool,
+}
+
+impl JsonlStore {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| e.to_string())?;
+
+        let contents = std::fs::read(path).map_err(|e| e.to_string())?;
+        let mut memory = MemoryStore::new();
+        let mut replayed = 0;
+        for (index, line) in contents.split(|&b| b == b'\n').enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            // Only the last line can be torn, by a crash mid-append
+            match serde_json::from_slice::<DbWrite>(line) {
+                Ok(write) => {
+                    memory.apply(&write);
+                    replayed += 1;
+                }
+                Err(e) => tracing::warn!("Skipping line {} of {}: {}", index + 1, path, e),
+            }
+        }
+        tracing::info!("Replayed {} DB messages from {}", replayed, path);
+
+        // End a torn line so the next append starts on a fresh one
+        if contents.last().is_some_and(|&b| b != b'\n') {
+            file.write_all(b"\n").map_err(|e| e.to_string())?;
+        }
+
+        Ok(JsonlStore {
+            file,
+            memory,
+            torn: false,
+        })
+    }
+}
+
+impl Storage for JsonlStore {
+    /// New writes go out in a single append and are synced before they are
+    /// applied in memory.
+    fn write_batch(&mut self, writes: &[DbWrite]) -> Result<usize, WriteError> {
+        let mut batch_ids = HashSet::new();
+        let fresh: Vec<&DbWrite> = writes
+            .iter()
+            .filter(|write| !self.memory.has_applied(&write.id) && batch_ids.insert(&write.id))
+            .collect();
+        if fresh.is_empty() {
+            return Ok(0);
+        }
+
+        let mut buffer = Vec::new();
+        if self.torn {
+            buffer.push(b'\n');
+        }
+        for write in &fresh {
+            serde_json::to_writer(&mut buffer, write)
+                .map_err(|e| WriteError::Rejected(e.to_string()))?;
+            buffer.push(b'\n');
+        }
+        self.file
+            .write_all(&buffer)
+            .and_then(|()| self.file.sync_data())
+            .map_err(|e| {
+                self.torn = true;
+                WriteError::Unavailable(e.to_string())
+            })?;
+        self.torn = false;
+        for write in fresh {
+            self.memory.apply(write);
+        }
+        Ok(batch_ids.len())
+    }
+
+    /// The file keeps every id; only the in-memory set used to skip
+    /// duplicates is cleared.
+    fn forget_applied(&mut self) -> Result<(), String> {
+        self.memory.forget_applied()
+    }
+
+    fn order_history(&self, filter: &HistoryFilter) -> Result<Page<Order>, String> {
+        self.memory.order_history(filter)
+    }
+
+    fn trade_history(&self, filter: &HistoryFilter) -> Result<Page<Trade>, String> {
+        self.memory.trade_history(filter)
+    }
+
+    fn last_ids(&self) -> Result<LastIds, String> {
+        self.memory.last_ids()
+    }
+
+    fn accounts(&self) -> Result<StoredAccounts, String> {
+        self.memory.accounts()
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{
+    db::{
+        query,
+        storage::{Storage, WriteError},
+    },
+    types::{
+        account::Transfer,
+        admin::AuditEntry,
+        db::{DbMessage, DbWrite, HistoryFilter, LastIds, Page, StoredAccounts},
+        ledger::JournalEntry,
+        market::Market,
+        order::{Order, OrderStatus, Trade},
+    },
+};
+use std::collections::{HashMap, HashSet};
+
+/// Everything held in maps; lost on restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    orders: HashMap<u64, Order>,
+    trades: Vec<Trade>,
+    markets: HashMap<String, Market>,
+    balances: HashMap<u32, f64>,
+    audit: Vec<AuditEntry>,
+    transfers: Vec<Transfer>,
+    journal: Vec<JournalEntry>,
+    applied: HashSet<String>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Applies `write` unless its id has been seen; false if it has.
+    pub fn apply(&mut self, write: &DbWrite) -> bool {
+        if !self.applied.insert(write.id.clone()) {
+            return false;
+        }
+        self.apply_message(write.message.clone());
+        true
+    }
+
+    pub fn has_applied(&self, id: &str) -> bool {
+        self.applied.contains(id)
+    }
+
+    fn apply_message(&mut self, message: DbMessage) {
+        match message {
+            DbMessage::SaveOrder(order) => {
+                self.orders.insert(order.id, order);
+            }
+            DbMessage::SaveTrade(trade) => self.trades.push(trade),
+            DbMessage::SaveMarket(market) => {
+                self.markets.insert(market.market_id.clone(), market);
+            }
+            DbMessage::UpdateBalance { user_id, balance } => {
+                self.balances.insert(user_id, balance);
+            }
+            DbMessage::SaveAudit(entry) => self.audit.push(entry),
+            DbMessage::SaveTransfer(transfer) => self.transfers.push(transfer),
+            DbMessage::SaveJournal(entry) => self.journal.push(entry),
+        }
+    }
+}
+
+impl Storage for MemoryStore {
+    fn write_batch(&mut self, writes: &[DbWrite]) -> Result<usize, WriteError> {
+        Ok(writes.iter().filter(|write| self.apply(write)).count())
+    }
+
+    fn forget_applied(&mut self) -> Result<(), String> {
+        self.applied.clear();
+        Ok(())
+    }
+
+    fn order_history(&self, filter: &HistoryFilter) -> Result<Page<Order>, String> {
+        Ok(query::order_history(&self.orders, filter))
+    }
+
+    fn trade_history(&self, filter: &HistoryFilter) -> Result<Page<Trade>, String> {
+        Ok(query::trade_history(&self.trades, &self.orders, filter))
+    }
+
+    fn last_ids(&self) -> Result<LastIds, String> {
+        let trade_orders = self
+            .trades
+            .iter()
+            .flat_map(|t| [t.buy_order_id, t.sell_order_id]);
+        Ok(LastIds {
+            order: self
+                .orders
+                .keys()
+                .copied()
+                .chain(trade_orders)
+                .max()
+                .unwrap_or(0),
+            transfer: self.transfers.iter().map(|t| t.id).max().unwrap_or(0),
+            audit: self.audit.iter().map(|e| e.id).max().unwrap_or(0),
+            journal: self.journal.iter().map(|e| e.id).max().unwrap_or(0),
+        })
+    }
+
+    fn accounts(&self) -> Result<StoredAccounts, String> {
+        let mut ledger = HashMap::new();
+        for posting in self.journal.iter().flat_map(|e| &e.postings) {
+            *ledger.entry(posting.account).or_default() += posting.amount;
+        }
+        Ok(StoredAccounts {
+            user_ids: self.balances.keys().copied().collect(),
+            ledger,
+            open_orders: self
+                .orders
+                .values()
+                .filter(|o| matches!(o.status, OrderStatus::Open | OrderStatus::PartiallyFilled))
+                .cloned()
+                .collect(),
+        })
+    }
+}
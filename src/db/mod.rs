@@ -1 +1,7 @@
+pub mod jsonl;
+pub mod memory;
 pub mod processor;
+pub mod query;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod storage;
@@ -1,35 +1,79 @@
-use crate::{redis::manager::RedisManager, types::db::DbMessage};
-use std::collections::HashMap;
-use tokio::sync::RwLock;
+use crate::{
+    db::storage::{Storage, WriteError},
+    redis::manager::{Claimed, RedisManager},
+    types::db::{DbQuery, DbRequest, DbResponse, DbWrite, LastIds, StoredAccounts},
+};
+use std::{collections::HashSet, sync::Mutex, time::Duration};
+
+// Most writes claimed from db_queue and committed in one transaction
+const WRITE_BATCH: usize = 256;
+// Claimed writes wait here until they are committed. There is one DB
+// processor per queue, so everything on it belongs to the current batch
+const PROCESSING: &str = "db_queue:processing";
+// Writes the store rejected even on their own and payloads that no longer
+// deserialize, kept for inspection
+const FAILED: &str = "db_queue:failed";
+// Bounds on the wait before retrying a batch the store could not take
+const RETRY_MIN: Duration = Duration::from_millis(100);
+const RETRY_MAX: Duration = Duration::from_secs(30);
 
 pub struct DbProcessor {
     redis: RedisManager,
-    orders: RwLock<HashMap<u64, crate::types::order::Order>>,
-    trades: RwLock<Vec<crate::types::order::Trade>>,
-    markets: RwLock<HashMap<String, crate::types::market::Market>>,
-    balances: RwLock<HashMap<u32, f64>>,
+    store: Mutex<Box<dyn Storage>>,
 }
 
 impl DbProcessor {
-    pub fn new(redis: RedisManager) -> Self {
+    pub fn new(redis: RedisManager, store: Box<dyn Storage>) -> Self {
         DbProcessor {
             redis,
-            orders: RwLock::new(HashMap::new()),
-            trades: RwLock::new(Vec::new()),
-            markets: RwLock::new(HashMap::new()),
-            balances: RwLock::new(HashMap::new()),
+            store: Mutex::new(store),
         }
     }
 
+    /// Applies writes from `db_queue` and answers queries from `db_queries`
+    /// side by side, so reads never wait behind a write backlog.
     pub async fn run(&self) {
+        tokio::join!(self.run_writes(), self.run_queries());
+    }
+
+    /// Commits everything already queued. Run before the engine starts, it
+    /// leaves the store holding every write the engine made before a restart.
+    pub async fn catch_up(&self) -> Result<(), String> {
+        self.replay_claimed().await;
         loop {
-            match self.redis.pop_message::<DbMessage>("db_queue").await {
-                Ok(Some(message)) => {
-                    if let Err(e) = self.process(message).await {
-                        tracing::error!("Error processing DB message: {}", e);
-                    }
-                }
-                Ok(None) => continue,
+            let batch = self
+                .redis
+                .claim_batch::<DbWrite>("db_queue", PROCESSING, WRITE_BATCH)
+                .await
+                .map_err(|e| e.to_string())?;
+            if batch.is_empty() {
+                return Ok(());
+            }
+            self.commit(batch).await;
+        }
+    }
+
+    pub fn last_ids(&self) -> Result<LastIds, String> {
+        self.store().last_ids()
+    }
+
+    pub fn accounts(&self) -> Result<StoredAccounts, String> {
+        self.store().accounts()
+    }
+
+    /// Writes are acknowledged only once the store has committed them, so a
+    /// crash replays the unacknowledged batch on the next start and the
+    /// store's id check drops whatever had already landed.
+    async fn run_writes(&self) {
+        self.replay_claimed().await;
+        loop {
+            match self
+                .redis
+                .claim_batch::<DbWrite>("db_queue", PROCESSING, WRITE_BATCH)
+                .await
+            {
+                Ok(batch) if batch.is_empty() => continue,
+                Ok(batch) => self.commit(batch).await,
                 Err(e) => {
                     tracing::error!("Redis error: {}", e);
                     tokio::time::sleep(std::time::Duration::from_secs(1)).await;
@@ -38,24 +82,158 @@ impl DbProcessor {
         }
     }
 
-    async fn process(&self, message: DbMessage) -> Result<(), String> {
-        match message {
-            DbMessage::SaveOrder(order) => {
-                self.orders.write().await.insert(order.id, order);
+    async fn replay_claimed(&self) {
+        match self.redis.claimed::<DbWrite>(PROCESSING).await {
+            Ok(batch) if batch.is_empty() => {}
+            Ok(batch) => {
+                tracing::info!(
+                    "Replaying {} unacknowledged DB writes",
+                    batch.messages.len() + batch.unreadable.len()
+                );
+                self.commit(batch).await;
             }
-            DbMessage::SaveTrade(trade) => {
-                self.trades.write().await.push(trade);
+            Err(e) => tracing::error!("Failed to read unacknowledged DB writes: {}", e),
+        }
+    }
+
+    // The batch stays claimed until every write in it has either landed or
+    // been parked. Payloads that no longer deserialize are parked as they
+    // are. While the store is unavailable the whole batch is retried with a
+    // growing delay; a batch it rejects is retried one write at a time so
+    // only the writes that fail on their own are parked
+    async fn commit(&self, batch: Claimed<DbWrite>) {
+        let mut delay = RETRY_MIN;
+        for raw in &batch.unreadable {
+            while let Err(e) = self.redis.push_raw(FAILED, raw).await {
+                tracing::error!(
+                    "Failed to park unreadable DB write, retrying in {:?}: {}",
+                    delay,
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(RETRY_MAX);
+            }
+        }
+
+        let mut parked = HashSet::new();
+        loop {
+            let pending: Vec<DbWrite> = batch
+                .messages
+                .iter()
+                .filter(|write| !parked.contains(&write.id))
+                .cloned()
+                .collect();
+            let result = self.store().write_batch(&pending);
+            let result = match result {
+                Ok(applied) => {
+                    if applied < pending.len() {
+                        tracing::info!("Skipped {} duplicate DB writes", pending.len() - applied);
+                    }
+                    Ok(())
+                }
+                Err(WriteError::Rejected(e)) => {
+                    tracing::error!("Error writing {} DB messages: {}", pending.len(), e);
+                    self.commit_singly(&pending, &mut parked).await
+                }
+                Err(e) => Err(e.to_string()),
+            };
+            match result {
+                Ok(()) => break,
+                Err(e) => {
+                    tracing::error!("DB writes not committed, retrying in {:?}: {}", delay, e);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(RETRY_MAX);
+                }
             }
-            DbMessage::SaveMarket(market) => {
-                self.markets
-                    .write()
-                    .await
-                    .insert(market.market_id.clone(), market);
+        }
+
+        match self.redis.ack(PROCESSING).await {
+            Ok(()) => {
+                if let Err(e) = self.store().forget_applied() {
+                    tracing::error!("Failed to drop applied DB write ids: {}", e);
+                }
             }
-            DbMessage::UpdateBalance { user_id, balance } => {
-                self.balances.write().await.insert(user_id, balance);
+            // Left unacknowledged, the batch is replayed and deduplicated on
+            // restart
+            Err(e) => tracing::error!("Failed to acknowledge DB writes: {}", e),
+        }
+    }
+
+    // Applies `writes` one by one, parking the ones the store rejects. Stops
+    // at the first write that can't be placed either way
+    async fn commit_singly(
+        &self,
+        writes: &[DbWrite],
+        parked: &mut HashSet<String>,
+    ) -> Result<(), String> {
+        for write in writes {
+            let result = self.store().write_batch(std::slice::from_ref(write));
+            match result {
+                Ok(_) => {}
+                Err(WriteError::Rejected(e)) => {
+                    tracing::error!("Parking DB write {}: {}", write.id, e);
+                    self.redis
+                        .push_message(FAILED, write)
+                        .await
+                        .map_err(|e| format!("Failed to park DB write {}: {}", write.id, e))?;
+                    parked.insert(write.id.clone());
+                }
+                Err(e) => return Err(e.to_string()),
             }
         }
         Ok(())
     }
+
+    async fn run_queries(&self) {
+        loop {
+            match self.redis.pop_message::<DbRequest>("db_queries").await {
+                Ok(Some(request)) => {
+                    let response = self.answer(request).await;
+                    if let Err(e) = self.redis.publish_message("db_responses", &response).await {
+                        tracing::error!("Failed to publish DB response: {}", e);
+                    }
+                }
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::error!("Redis error: {}", e);
+                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+
+    async fn answer(&self, request: DbRequest) -> DbResponse {
+        let client_id = request.client_id;
+        let store = self.store();
+        let result = match request.query {
+            DbQuery::OrderHistory(filter) => {
+                store.order_history(&filter).map(|page| DbResponse::Orders {
+                    page,
+                    client_id: client_id.clone(),
+                })
+            }
+            DbQuery::TradeHistory(filter) => {
+                store.trade_history(&filter).map(|page| DbResponse::Trades {
+                    page,
+                    client_id: client_id.clone(),
+                })
+            }
+            DbQuery::RecentTrades { market_id, limit } => store
+                .recent_trades(&market_id, limit)
+                .map(|page| DbResponse::Trades {
+                    page,
+                    client_id: client_id.clone(),
+                }),
+        };
+        result.unwrap_or_else(|message| {
+            tracing::error!("DB query failed: {}", message);
+            DbResponse::Error { message, client_id }
+        })
+    }
+
+    fn store(&self) -> std::sync::MutexGuard<'_, Box<dyn Storage>> {
+        // A panic mid-write leaves the store itself usable; the SQLite
+        // backend rolls back any open transaction when it drops
+        self.store.lock().unwrap_or_else(|e| e.into_inner())
+    }
 }
@@ -0,0 +1,66 @@
+use crate::{
+    db::storage::page_limit,
+    types::{
+        db::{HistoryFilter, Page},
+        order::{Order, Trade},
+    },
+};
+use std::collections::HashMap;
+
+fn paginate<T: Clone>(matching: Vec<&T>, offset: usize, limit: Option<usize>) -> Page<T> {
+    let limit = page_limit(limit);
+    Page {
+        total: matching.len(),
+        items: matching
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect(),
+        offset,
+        limit,
+    }
+}
+
+fn in_range(filter: &HistoryFilter, timestamp: u64) -> bool {
+    filter.from.is_none_or(|from| timestamp >= from) && filter.to.is_none_or(|to| timestamp <= to)
+}
+
+pub fn order_history(orders: &HashMap<u64, Order>, filter: &HistoryFilter) -> Page<Order> {
+    let mut matching: Vec<&Order> = orders
+        .values()
+        .filter(|o| filter.user_id.is_none_or(|user_id| o.user_id == user_id))
+        .filter(|o| filter.market_id.as_ref().is_none_or(|m| &o.market_id == m))
+        .filter(|o| filter.option.is_none_or(|option| o.option == option))
+        .filter(|o| in_range(filter, o.timestamp))
+        .collect();
+    matching.sort_by_key(|o| std::cmp::Reverse((o.timestamp, o.id)));
+    paginate(matching, filter.offset, filter.limit)
+}
+
+/// Trades carry no user ids, so a user's trades are found through the
+/// orders on either side.
+pub fn trade_history(
+    trades: &[Trade],
+    orders: &HashMap<u64, Order>,
+    filter: &HistoryFilter,
+) -> Page<Trade> {
+    let owned_by = |order_id: u64, user_id: u32| {
+        orders
+            .get(&order_id)
+            .is_some_and(|order| order.user_id == user_id)
+    };
+    let matching: Vec<&Trade> = trades
+        .iter()
+        .rev()
+        .filter(|t| {
+            filter.user_id.is_none_or(|user_id| {
+                owned_by(t.buy_order_id, user_id) || owned_by(t.sell_order_id, user_id)
+            })
+        })
+        .filter(|t| filter.market_id.as_ref().is_none_or(|m| &t.market_id == m))
+        .filter(|t| filter.option.is_none_or(|option| t.option == option))
+        .filter(|t| in_range(filter, t.timestamp))
+        .collect();
+    paginate(matching, filter.offset, filter.limit)
+}
@@ -0,0 +1,453 @@
+use crate::{
+    db::storage::{Storage, WriteError, page_limit},
+    types::{
+        db::{DbMessage, DbWrite, HistoryFilter, LastIds, Page, StoredAccounts},
+        ledger::JournalEntry,
+        order::{Order, Trade},
+    },
+};
+use rusqlite::{Connection, Row, params, params_from_iter, types::Value};
+use serde::{Serialize, de::DeserializeOwned};
+use std::collections::HashMap;
+
+/// Schema changes in order. A database at `user_version` n has had the first
+/// n applied; append new steps, never edit old ones.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE orders (
+        id INTEGER PRIMARY KEY,
+        user_id INTEGER NOT NULL,
+        market_id TEXT NOT NULL,
+        option TEXT NOT NULL,
+        order_type TEXT NOT NULL,
+        price REAL NOT NULL,
+        quantity INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL
+    );
+    CREATE INDEX orders_by_user ON orders (user_id, timestamp);
+    CREATE INDEX orders_by_market ON orders (market_id, timestamp);
+
+    CREATE TABLE trades (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        buy_order_id INTEGER NOT NULL,
+        sell_order_id INTEGER NOT NULL,
+        market_id TEXT NOT NULL,
+        option TEXT NOT NULL,
+        price REAL NOT NULL,
+        quantity INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL
+    );
+    CREATE INDEX trades_by_market ON trades (market_id, timestamp);
+    CREATE INDEX trades_by_buy_order ON trades (buy_order_id);
+    CREATE INDEX trades_by_sell_order ON trades (sell_order_id);
+
+    CREATE TABLE markets (market_id TEXT PRIMARY KEY, data TEXT NOT NULL);
+    CREATE TABLE balances (user_id INTEGER PRIMARY KEY, balance REAL NOT NULL);
+    CREATE TABLE audit (id INTEGER PRIMARY KEY, data TEXT NOT NULL);
+    CREATE TABLE transfers (
+        id INTEGER PRIMARY KEY,
+        user_id INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX transfers_by_user ON transfers (user_id);
+    CREATE TABLE journal (id INTEGER PRIMARY KEY, data TEXT NOT NULL);",
+    "ALTER TABLE orders ADD COLUMN status TEXT NOT NULL DEFAULT 'Open';
+    ALTER TABLE orders ADD COLUMN filled_quantity INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE orders ADD COLUMN avg_fill_price REAL NOT NULL DEFAULT 0;",
+    "CREATE TABLE applied (id TEXT PRIMARY KEY) WITHOUT ROWID;",
+];
+
+/// Local SQLite database behind the DB processor.
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let mut conn = Connection::open(path).map_err(|e| e.to_string())?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(|e| e.to_string())?;
+        migrate(&mut conn)?;
+        Ok(SqliteStore { conn })
+    }
+
+    fn count(&self, table: &str, clause: &str, values: &[Value]) -> Result<usize, String> {
+        let sql = format!("SELECT COUNT(*) FROM {} {}", table, clause);
+        self.conn
+            .query_row(&sql, params_from_iter(values), |row| row.get::<_, i64>(0))
+            .map(|count| count as usize)
+            .map_err(|e| e.to_string())
+    }
+
+    fn select<T>(
+        &self,
+        sql: &str,
+        values: &[Value],
+        read: fn(&Row) -> rusqlite::Result<T>,
+    ) -> Result<Vec<T>, String> {
+        let mut statement = self.conn.prepare(sql).map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params_from_iter(values), read)
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<Vec<T>>>()
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl Storage for SqliteStore {
+    /// Applies the batch in a single transaction, together with the ids it
+    /// adds to `applied`; nothing lands if any write fails.
+    fn write_batch(&mut self, writes: &[DbWrite]) -> Result<usize, WriteError> {
+        let tx = self.conn.transaction().map_err(write_error)?;
+        let mut applied = 0;
+        for entry in writes {
+            let fresh = tx
+                .execute(
+                    "INSERT OR IGNORE INTO applied (id) VALUES (?1)",
+                    [&entry.id],
+                )
+                .map_err(write_error)?;
+            if fresh == 1 {
+                write(&tx, &entry.message).map_err(write_error)?;
+                applied += 1;
+            }
+        }
+        tx.commit().map_err(write_error)?;
+        Ok(applied)
+    }
+
+    fn forget_applied(&mut self) -> Result<(), String> {
+        self.conn
+            .execute("DELETE FROM applied", [])
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn order_history(&self, filter: &HistoryFilter) -> Result<Page<Order>, String> {
+        let (clause, mut values) = filter_clause(filter, "user_id = ?1");
+        let total = self.count("orders", &clause, &values)?;
+        let limit = page_limit(filter.limit);
+        values.push(Value::Integer(limit as i64));
+        values.push(Value::Integer(filter.offset as i64));
+        let sql = format!(
+            "SELECT id, user_id, market_id, option, order_type, price, quantity, timestamp,
+                    status, filled_quantity, avg_fill_price
+             FROM orders {} ORDER BY timestamp DESC, id DESC LIMIT ? OFFSET ?",
+            clause
+        );
+        let items = self.select(&sql, &values, read_order)?;
+        Ok(Page {
+            items,
+            total,
+            offset: filter.offset,
+            limit,
+        })
+    }
+
+    fn trade_history(&self, filter: &HistoryFilter) -> Result<Page<Trade>, String> {
+        let by_user = "(buy_order_id IN (SELECT id FROM orders WHERE user_id = ?1)
+             OR sell_order_id IN (SELECT id FROM orders WHERE user_id = ?1))";
+        let (clause, mut values) = filter_clause(filter, by_user);
+        let total = self.count("trades", &clause, &values)?;
+        let limit = page_limit(filter.limit);
+        values.push(Value::Integer(limit as i64));
+        values.push(Value::Integer(filter.offset as i64));
+        let sql = format!(
+            "SELECT buy_order_id, sell_order_id, market_id, option, price, quantity, timestamp
+             FROM trades {} ORDER BY id DESC LIMIT ? OFFSET ?",
+            clause
+        );
+        let items = self.select(&sql, &values, read_trade)?;
+        Ok(Page {
+            items,
+            total,
+            offset: filter.offset,
+            limit,
+        })
+    }
+
+    fn last_ids(&self) -> Result<LastIds, String> {
+        let max = |sql: &str| {
+            self.conn
+                .query_row(sql, [], |row| row.get::<_, Option<i64>>(0))
+                .map(|id| id.unwrap_or(0) as u64)
+                .map_err(|e| e.to_string())
+        };
+        Ok(LastIds {
+            order: max("SELECT MAX(id) FROM (
+                 SELECT MAX(id) AS id FROM orders
+                 UNION ALL SELECT MAX(buy_order_id) FROM trades
+                 UNION ALL SELECT MAX(sell_order_id) FROM trades)")?,
+            transfer: max("SELECT MAX(id) FROM transfers")?,
+            audit: max("SELECT MAX(id) FROM audit")?,
+            journal: max("SELECT MAX(id) FROM journal")?,
+        })
+    }
+
+    fn accounts(&self) -> Result<StoredAccounts, String> {
+        let user_ids = self.select("SELECT user_id FROM balances", &[], |row| row.get(0))?;
+        let mut ledger = HashMap::new();
+        let entries: Vec<String> =
+            self.select("SELECT data FROM journal", &[], |row| row.get(0))?;
+        for data in entries {
+            let entry: JournalEntry = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+            for posting in entry.postings {
+                *ledger.entry(posting.account).or_default() += posting.amount;
+            }
+        }
+        let open_orders = self.select(
+            "SELECT id, user_id, market_id, option, order_type, price, quantity, timestamp,
+                    status, filled_quantity, avg_fill_price
+             FROM orders WHERE status IN ('Open', 'PartiallyFilled')",
+            &[],
+            read_order,
+        )?;
+        Ok(StoredAccounts {
+            user_ids,
+            ledger,
+            open_orders,
+        })
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), String> {
+    let version: usize = conn
+        .query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))
+        .map_err(|e| e.to_string())? as usize;
+    if version > MIGRATIONS.len() {
+        return Err(format!(
+            "Database schema version {} is newer than this build supports",
+            version
+        ));
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        tx.execute_batch(migration).map_err(|e| e.to_string())?;
+        tx.pragma_update(None, "user_version", (index + 1) as i64)
+            .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        tracing::info!("Applied database migration {}", index + 1);
+    }
+    Ok(())
+}
+
+// Orders, markets and balances are current state and overwritten in place;
+// audit, transfer and journal rows are history, so a clashing id is an error
+fn write(conn: &Connection, message: &DbMessage) -> rusqlite::Result<usize> {
+    match message {
+        DbMessage::SaveOrder(order) => conn.execute(
+            "INSERT OR REPLACE INTO orders
+             (id, user_id, market_id, option, order_type, price, quantity, timestamp,
+              status, filled_quantity, avg_fill_price)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                order.id as i64,
+                order.user_id,
+                order.market_id,
+                to_text(&order.option),
+                to_text(&order.order_type),
+                order.price,
+                order.quantity,
+                order.timestamp as i64,
+                to_text(&order.status),
+                order.filled_quantity,
+                order.avg_fill_price,
+            ],
+        ),
+        DbMessage::SaveTrade(trade) => conn.execute(
+            "INSERT INTO trades
+             (buy_order_id, sell_order_id, market_id, option, price, quantity, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                trade.buy_order_id as i64,
+                trade.sell_order_id as i64,
+                trade.market_id,
+                to_text(&trade.option),
+                trade.price,
+                trade.quantity,
+                trade.timestamp as i64,
+            ],
+        ),
+        DbMessage::SaveMarket(market) => conn.execute(
+            "INSERT OR REPLACE INTO markets (market_id, data) VALUES (?1, ?2)",
+            params![market.market_id, to_json(market)],
+        ),
+        DbMessage::UpdateBalance { user_id, balance } => conn.execute(
+            "INSERT OR REPLACE INTO balances (user_id, balance) VALUES (?1, ?2)",
+            params![user_id, balance],
+        ),
+        DbMessage::SaveAudit(entry) => conn.execute(
+            "INSERT INTO audit (id, data) VALUES (?1, ?2)",
+            params![entry.id as i64, to_json(entry)],
+        ),
+        DbMessage::SaveTransfer(transfer) => conn.execute(
+            "INSERT INTO transfers (id, user_id, data) VALUES (?1, ?2, ?3)",
+            params![transfer.id as i64, transfer.user_id, to_json(transfer)],
+        ),
+        DbMessage::SaveJournal(entry) => conn.execute(
+            "INSERT INTO journal (id, data) VALUES (?1, ?2)",
+            params![entry.id as i64, to_json(entry)],
+        ),
+    }
+}
+
+// A constraint or a value SQLite won't take fails the same way every time;
+// anything else, such as a busy database or a full disk, may clear
+fn write_error(error: rusqlite::Error) -> WriteError {
+    let rejected = match &error {
+        rusqlite::Error::SqliteFailure(failure, _) => matches!(
+            failure.code,
+            rusqlite::ErrorCode::ConstraintViolation | rusqlite::ErrorCode::TooBig
+        ),
+        rusqlite::Error::ToSqlConversionFailure(_) => true,
+        _ => false,
+    };
+    if rejected {
+        WriteError::Rejected(error.to_string())
+    } else {
+        WriteError::Unavailable(error.to_string())
+    }
+}
+
+// WHERE clause and its bound values for `filter`; `by_user` is the user
+// condition for the table being queried and must bind the user id once
+fn filter_clause(filter: &HistoryFilter, by_user: &str) -> (String, Vec<Value>) {
+    let mut conditions = Vec::new();
+    let mut values = Vec::new();
+    if let Some(user_id) = filter.user_id {
+        conditions.push(by_user.to_string());
+        values.push(Value::Integer(user_id as i64));
+    }
+    if let Some(market_id) = &filter.market_id {
+        conditions.push(format!("market_id = ?{}", values.len() + 1));
+        values.push(Value::Text(market_id.clone()));
+    }
+    if let Some(option) = &filter.option {
+        conditions.push(format!("option = ?{}", values.len() + 1));
+        values.push(Value::Text(to_text(option)));
+    }
+    if let Some(from) = filter.from {
+        conditions.push(format!("timestamp >= ?{}", values.len() + 1));
+        values.push(Value::Integer(from as i64));
+    }
+    if let Some(to) = filter.to {
+        conditions.push(format!("timestamp <= ?{}", values.len() + 1));
+        values.push(Value::Integer(to as i64));
+    }
+    if conditions.is_empty() {
+        return (String::new(), values);
+    }
+    (format!("WHERE {}", conditions.join(" AND ")), values)
+}
+
+fn read_order(row: &Row) -> rusqlite::Result<Order> {
+    Ok(Order {
+        id: row.get::<_, i64>(0)? as u64,
+        user_id: row.get(1)?,
+        market_id: row.get(2)?,
+        option: from_text(row.get(3)?)?,
+        order_type: from_text(row.get(4)?)?,
+        price: row.get(5)?,
+        quantity: row.get(6)?,
+        timestamp: row.get::<_, i64>(7)? as u64,
+        status: from_text(row.get(8)?)?,
+        filled_quantity: row.get(9)?,
+        avg_fill_price: row.get(10)?,
+    })
+}
+
+fn read_trade(row: &Row) -> rusqlite::Result<Trade> {
+    Ok(Trade {
+        buy_order_id: row.get::<_, i64>(0)? as u64,
+        sell_order_id: row.get::<_, i64>(1)? as u64,
+        market_id: row.get(2)?,
+        option: from_text(row.get(3)?)?,
+        price: row.get(4)?,
+        quantity: row.get(5)?,
+        timestamp: row.get::<_, i64>(6)? as u64,
+    })
+}
+
+// Unit enums are stored by variant name, e.g. "Yes" or "Buy"
+fn to_text<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(text)) => text,
+        other => format!("{:?}", other),
+    }
+}
+
+fn from_text<T: DeserializeOwned>(text: String) -> rusqlite::Result<T> {
+    serde_json::from_value(serde_json::Value::String(text)).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, e.into())
+    })
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("Failed to serialize record")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        account::{Transfer, TransferKind},
+        order::{OptionType, OrderType},
+    };
+
+    fn transfer(id: u64) -> DbWrite {
+        DbWrite::new(DbMessage::SaveTransfer(Transfer {
+            id,
+            user_id: 1,
+            kind: TransferKind::Deposit,
+            amount: 10.0,
+            available: 10.0,
+            locked: 0.0,
+            timestamp: 0,
+        }))
+    }
+
+    #[test]
+    fn last_ids_cover_what_was_stored() {
+        let mut store = SqliteStore::open(":memory:").unwrap();
+        assert_eq!(store.last_ids().unwrap(), LastIds::default());
+
+        let order = Order::new(
+            7,
+            1,
+            "m".to_string(),
+            OptionType::Yes,
+            OrderType::Buy,
+            5.0,
+            1,
+        );
+        store
+            .write_batch(&[DbWrite::new(DbMessage::SaveOrder(order)), transfer(3)])
+            .unwrap();
+        let last = store.last_ids().unwrap();
+        assert_eq!((last.order, last.transfer), (7, 3));
+    }
+
+    #[test]
+    fn history_rows_are_never_overwritten() {
+        let mut store = SqliteStore::open(":memory:").unwrap();
+        store.write_batch(&[transfer(1)]).unwrap();
+        assert!(matches!(
+            store.write_batch(&[transfer(1)]),
+            Err(WriteError::Rejected(_))
+        ));
+    }
+
+    #[test]
+    fn acknowledged_ids_are_forgotten() {
+        let mut store = SqliteStore::open(":memory:").unwrap();
+        let write = transfer(1);
+        assert_eq!(store.write_batch(std::slice::from_ref(&write)).unwrap(), 1);
+        assert_eq!(store.write_batch(std::slice::from_ref(&write)).unwrap(), 0);
+
+        store.forget_applied().unwrap();
+        let left: i64 = store
+            .conn
+            .query_row("SELECT COUNT(*) FROM applied", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(left, 0);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{
+    db::{jsonl::JsonlStore, memory::MemoryStore},
+    types::{
+        db::{DbWrite, HistoryFilter, LastIds, Page, StoredAccounts},
+        order::{Order, Trade},
+    },
+};
+use std::{fmt, str::FromStr};
+
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 1000;
+
+/// Why a batch of writes did not land.
+#[derive(Clone, Debug)]
+pub enum WriteError {
+    /// The store can't take writes right now, e.g. the disk is full or the
+    /// database is locked; the same writes may go through later.
+    Unavailable(String),
+    /// Some write in the batch can never be applied, e.g. its id clashes
+    /// with a stored record.
+    Rejected(String),
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::Unavailable(e) => write!(f, "store unavailable: {}", e),
+            WriteError::Rejected(e) => write!(f, "write rejected: {}", e),
+        }
+    }
+}
+
+/// Where the DB processor keeps what it is sent. Backends differ only in
+/// durability; the processor loop treats them all the same. Applied write ids
+/// are kept exactly as durably as the data they came with, until the queue
+/// has acknowledged them.
+pub trait Storage: Send {
+    /// Applies the writes whose ids it has not seen before as one unit and
+    /// returns how many there were. A backend that can fail part way must
+    /// leave none of them applied.
+    fn write_batch(&mut self, writes: &[DbWrite]) -> Result<usize, WriteError>;
+
+    /// Drops the ids of the writes applied so far. Called once they are
+    /// acknowledged on the queue: an acknowledged write is never delivered
+    /// again, so its id is no longer needed to spot a duplicate.
+    fn forget_applied(&mut self) -> Result<(), String>;
+
+    /// Newest first.
+    fn order_history(&self, filter: &HistoryFilter) -> Result<Page<Order>, String>;
+
+    /// Newest first. Trades carry no user ids, so a user's trades are found
+    /// through the orders on either side.
+    fn trade_history(&self, filter: &HistoryFilter) -> Result<Page<Trade>, String>;
+
+    /// Highest ids stored so far, zero for kinds with none.
+    fn last_ids(&self) -> Result<LastIds, String>;
+
+    fn accounts(&self) -> Result<StoredAccounts, String>;
+
+    fn recent_trades(&self, market_id: &str, limit: usize) -> Result<Page<Trade>, String> {
+        self.trade_history(&HistoryFilter {
+            market_id: Some(market_id.to_string()),
+            limit: Some(limit),
+            ..HistoryFilter::default()
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StorageBackend {
+    /// Nothing survives a restart.
+    Memory,
+    /// Append-only file of every message, replayed on start.
+    Jsonl,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl StorageBackend {
+    pub fn default_path(&self) -> &'static str {
+        match self {
+            StorageBackend::Memory => "",
+            StorageBackend::Jsonl => "probo.jsonl",
+            #[cfg(feature = "sqlite")]
+            StorageBackend::Sqlite => "probo.db",
+        }
+    }
+
+    /// Opens the backend at `path`; ignored for `Memory`.
+    pub fn open(&self, path: &str) -> Result<Box<dyn Storage>, String> {
+        Ok(match self {
+            StorageBackend::Memory => Box::new(MemoryStore::new()),
+            StorageBackend::Jsonl => Box::new(JsonlStore::open(path)?),
+            #[cfg(feature = "sqlite")]
+            StorageBackend::Sqlite => Box::new(crate::db::sqlite::SqliteStore::open(path)?),
+        })
+    }
+}
+
+impl Default for StorageBackend {
+    // The most durable backend this build was compiled with
+    fn default() -> Self {
+        #[cfg(feature = "sqlite")]
+        return StorageBackend::Sqlite;
+        #[cfg(not(feature = "sqlite"))]
+        return StorageBackend::Jsonl;
+    }
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "memory" => Ok(StorageBackend::Memory),
+            "jsonl" => Ok(StorageBackend::Jsonl),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Ok(StorageBackend::Sqlite),
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite" => Err("This build was compiled without the sqlite feature".to_string()),
+            other => Err(format!("Unknown storage backend: {}", other)),
+        }
+    }
+}
+
+pub fn page_limit(limit: Option<usize>) -> usize {
+    limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+}
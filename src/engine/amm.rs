@@ -0,0 +1,180 @@
+use crate::{
+    engine::order_book::SHARE_PAYOUT,
+    types::{market_data::AmmSnapshot, order::OptionType},
+};
+
+/// Order id used as the counterparty of every AMM fill.
+pub const AMM_ORDER_ID: u64 = 0;
+
+/// Logarithmic market scoring rule maker for one binary market. `q_yes` and
+/// `q_no` are the shares it has sold; its worst-case loss is `b * ln 2 * 10`,
+/// which the house commits as subsidy when the maker is attached.
+#[derive(Clone, Debug)]
+pub struct LmsrMarketMaker {
+    pub b: f64,
+    pub q_yes: f64,
+    pub q_no: f64,
+    pub house: HouseAccount,
+}
+
+/// The house side of an AMM: subsidy committed and net cash taken from
+/// traders. The cash moves through the ledger's `House` account.
+#[derive(Clone, Debug, Default)]
+pub struct HouseAccount {
+    pub subsidy: f64,
+    pub cash: f64,
+}
+
+impl LmsrMarketMaker {
+    pub fn new(b: f64) -> Result<Self, String> {
+        if !b.is_finite() || b <= 0.0 {
+            return Err("AMM liquidity must be positive".to_string());
+        }
+        Ok(LmsrMarketMaker {
+            b,
+            q_yes: 0.0,
+            q_no: 0.0,
+            house: HouseAccount {
+                subsidy: b * std::f64::consts::LN_2 * SHARE_PAYOUT,
+                cash: 0.0,
+            },
+        })
+    }
+
+    fn cost(&self, q_yes: f64, q_no: f64) -> f64 {
+        // log-sum-exp, shifted by the max to stay finite for large q / b
+        let (a, c) = (q_yes / self.b, q_no / self.b);
+        let max = a.max(c);
+        SHARE_PAYOUT * self.b * (max + ((a - max).exp() + (c - max).exp()).ln())
+    }
+
+    fn quantities(&self, option: OptionType) -> (f64, f64) {
+        match option {
+            OptionType::Yes => (self.q_yes, self.q_no),
+            OptionType::No => (self.q_no, self.q_yes),
+        }
+    }
+
+    // What moving `option` by `delta` shares pays the maker
+    fn quote(&self, option: OptionType, delta: f64) -> f64 {
+        let (q_yes, q_no) = match option {
+            OptionType::Yes => (self.q_yes + delta, self.q_no),
+            OptionType::No => (self.q_yes, self.q_no + delta),
+        };
+        self.cost(q_yes, q_no) - self.cost(self.q_yes, self.q_no)
+    }
+
+    fn apply(&mut self, option: OptionType, delta: f64) -> f64 {
+        let paid = self.quote(option, delta);
+        match option {
+            OptionType::Yes => self.q_yes += delta,
+            OptionType::No => self.q_no += delta,
+        }
+        self.house.cash += paid;
+        paid
+    }
+
+    /// Marginal price of one share of `option`.
+    pub fn price(&self, option: OptionType) -> f64 {
+        let (this, other) = self.quantities(option);
+        SHARE_PAYOUT / (1.0 + ((other - this) / self.b).exp())
+    }
+
+    /// Largest whole quantity a buyer can take before the marginal price
+    /// would rise above `limit`.
+    pub fn max_buy(&self, option: OptionType, limit: f64) -> u32 {
+        let (this, other) = self.quantities(option);
+        let target = self.b * (limit / (SHARE_PAYOUT - limit)).ln();
+        (target - (this - other)).floor().max(0.0) as u32
+    }
+
+    /// Largest whole quantity a seller can hit before the marginal price
+    /// would fall below `limit`.
+    pub fn max_sell(&self, option: OptionType, limit: f64) -> u32 {
+        let (this, other) = self.quantities(option);
+        let target = self.b * (limit / (SHARE_PAYOUT - limit)).ln();
+        ((this - other) - target).floor().max(0.0) as u32
+    }
+
+    /// What `buy` would charge for `quantity` shares, without trading.
+    pub fn buy_cost(&self, option: OptionType, quantity: u32) -> f64 {
+        self.quote(option, quantity as f64)
+    }
+
+    /// What `sell` would pay for `quantity` shares, without trading.
+    pub fn sell_proceeds(&self, option: OptionType, quantity: u32) -> f64 {
+        -self.quote(option, -(quantity as f64))
+    }
+
+    /// Sells `quantity` shares to a trader and returns what the trader pays.
+    pub fn buy(&mut self, option: OptionType, quantity: u32) -> f64 {
+        self.apply(option, quantity as f64)
+    }
+
+    /// Buys `quantity` shares back from a trader and returns what the trader
+    /// receives.
+    pub fn sell(&mut self, option: OptionType, quantity: u32) -> f64 {
+        -self.apply(option, -(quantity as f64))
+    }
+
+    pub fn snapshot(&self, market_id: &str) -> AmmSnapshot {
+        let yes_price = self.price(OptionType::Yes);
+        let no_price = self.price(OptionType::No);
+        // Marked to the current prices, i.e. the expected payout on the
+        // shares outstanding
+        let liability = self.q_yes * yes_price + self.q_no * no_price;
+        AmmSnapshot {
+            market_id: market_id.to_string(),
+            b: self.b,
+            q_yes: self.q_yes,
+            q_no: self.q_no,
+            yes_price,
+            no_price,
+            subsidy: self.house.subsidy,
+            cash: self.house.cash,
+            pnl: self.house.cash - liability,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn liquidity_must_be_positive() {
+        assert!(LmsrMarketMaker::new(0.0).is_err());
+        assert!(LmsrMarketMaker::new(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn prices_move_with_inventory_and_sum_to_the_payout() {
+        let mut amm = LmsrMarketMaker::new(10.0).unwrap();
+        assert_eq!(amm.price(OptionType::Yes), 5.0);
+
+        let paid = amm.buy(OptionType::Yes, 5);
+        assert!(paid > 25.0 && paid < 5.0 * amm.price(OptionType::Yes));
+        assert!(amm.price(OptionType::Yes) > 5.0);
+        let sum = amm.price(OptionType::Yes) + amm.price(OptionType::No);
+        assert!((sum - SHARE_PAYOUT).abs() < 1e-9);
+
+        // Selling the same shares back pays out what they cost
+        let received = amm.sell(OptionType::Yes, 5);
+        assert!((received - paid).abs() < 1e-9);
+        assert!(amm.house.cash.abs() < 1e-9);
+    }
+
+    #[test]
+    fn max_buy_stops_at_the_limit_price() {
+        let amm = LmsrMarketMaker::new(10.0).unwrap();
+        let quantity = amm.max_buy(OptionType::Yes, 6.0);
+        assert_eq!(quantity, 4);
+
+        let mut filled = amm.clone();
+        filled.buy(OptionType::Yes, quantity);
+        assert!(filled.price(OptionType::Yes) <= 6.0);
+        filled.buy(OptionType::Yes, 1);
+        assert!(filled.price(OptionType::Yes) > 6.0);
+        assert_eq!(filled.max_sell(OptionType::Yes, 5.0), 5);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::{
+    engine::order_book::{COMPLEMENT_CENTS, OrderBook},
+    types::order::{OptionType, Order, OrderType},
+};
+
+/// One execution at the clearing price. `buyer` takes the Yes side (a Yes bid
+/// or a No ask) and `seller` the No side (a Yes ask or a No bid).
+#[derive(Clone, Debug)]
+pub struct AuctionFill {
+    pub buyer: Order,
+    pub seller: Order,
+    pub quantity: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Uncross {
+    /// Clearing price of Yes in cents; No clears at the complement.
+    pub price_cents: u64,
+    pub volume: u32,
+    pub fills: Vec<AuctionFill>,
+}
+
+// An order and the Yes price it is willing to trade at
+struct Interest<'a> {
+    order: &'a Order,
+    yes_cents: u64,
+}
+
+fn interests<'a>(
+    book: &'a OrderBook,
+    order_type: OrderType,
+    complement: bool,
+) -> impl Iterator<Item = Interest<'a>> {
+    let levels = match order_type {
+        OrderType::Buy => &book.bids,
+        OrderType::Sell => &book.asks,
+    };
+    levels.iter().flat_map(move |(&cents, queue)| {
+        let yes_cents = if complement {
+            COMPLEMENT_CENTS - cents
+        } else {
+            cents
+        };
+        queue.iter().map(move |order| Interest { order, yes_cents })
+    })
+}
+
+/// Finds the single Yes price that matches the most volume across both books
+/// and allocates it in price-time priority. A No ask at q is Yes demand at
+/// 10 - q and a No bid Yes supply. Ties go to the smallest imbalance, then to
+/// the price nearest `reference_cents`, else the median tied price.
+pub fn uncross(
+    yes_book: &OrderBook,
+    no_book: &OrderBook,
+    reference_cents: Option<u64>,
+) -> Option<Uncross> {
+    let mut buyers: Vec<Interest> = interests(yes_book, OrderType::Buy, false)
+        .chain(interests(no_book, OrderType::Sell, true))
+        .collect();
+    let mut sellers: Vec<Interest> = interests(yes_book, OrderType::Sell, false)
+        .chain(interests(no_book, OrderType::Buy, true))
+        .collect();
+
+    let mut candidates: Vec<u64> = buyers
+        .iter()
+        .chain(sellers.iter())
+        .map(|i| i.yes_cents)
+        .collect();
+    candidates.sort_unstable();
+    candidates.dedup();
+
+    // (volume, imbalance, price) at every candidate price
+    let stats: Vec<(u32, u32, u64)> = candidates
+        .iter()
+        .map(|&price| {
+            let demand: u32 = buyers
+                .iter()
+                .filter(|i| i.yes_cents >= price)
+                .map(|i| i.order.quantity)
+                .sum();
+            let supply: u32 = sellers
+                .iter()
+                .filter(|i| i.yes_cents <= price)
+                .map(|i| i.order.quantity)
+                .sum();
+            (demand.min(supply), demand.abs_diff(supply), price)
+        })
+        .collect();
+
+    let volume = stats.iter().map(|s| s.0).max().filter(|&v| v > 0)?;
+    let imbalance = stats.iter().filter(|s| s.0 == volume).map(|s| s.1).min()?;
+    let tied: Vec<u64> = stats
+        .iter()
+        .filter(|s| s.0 == volume && s.1 == imbalance)
+        .map(|s| s.2)
+        .collect();
+    let price_cents = match reference_cents {
+        Some(reference) => *tied.iter().min_by_key(|&&p| p.abs_diff(reference))?,
+        None => tied[tied.len() / 2],
+    };
+
+    buyers.retain(|i| i.yes_cents >= price_cents);
+    sellers.retain(|i| i.yes_cents <= price_cents);
+    buyers.sort_by_key(|i| {
+        (
+            std::cmp::Reverse(i.yes_cents),
+            i.order.timestamp,
+            i.order.id,
+        )
+    });
+    sellers.sort_by_key(|i| (i.yes_cents, i.order.timestamp, i.order.id));
+
+    let mut fills = Vec::new();
+    let mut left = volume;
+    let (mut b, mut s) = (0, 0);
+    let (mut buyer_left, mut seller_left) = (buyers[0].order.quantity, sellers[0].order.quantity);
+    while left > 0 {
+        let quantity = left.min(buyer_left).min(seller_left);
+        fills.push(AuctionFill {
+            buyer: buyers[b].order.clone(),
+            seller: sellers[s].order.clone(),
+            quantity,
+        });
+        left -= quantity;
+        buyer_left -= quantity;
+        seller_left -= quantity;
+        if buyer_left == 0 && left > 0 {
+            b += 1;
+            buyer_left = buyers[b].order.quantity;
+        }
+        if seller_left == 0 && left > 0 {
+            s += 1;
+            seller_left = sellers[s].order.quantity;
+        }
+    }
+
+    Some(Uncross {
+        price_cents,
+        volume,
+        fills,
+    })
+}
+
+/// Price in cents `order` executes at when Yes clears at `yes_cents`.
+pub fn execution_cents(order: &Order, yes_cents: u64) -> u64 {
+    match order.option {
+        OptionType::Yes => yes_cents,
+        OptionType::No => COMPLEMENT_CENTS - yes_cents,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(
+        id: u64,
+        option: OptionType,
+        order_type: OrderType,
+        price: f64,
+        quantity: u32,
+    ) -> Order {
+        Order::new(
+            id,
+            id as u32,
+            "m".to_string(),
+            option,
+            order_type,
+            price,
+            quantity,
+        )
+    }
+
+    #[test]
+    fn uncross_matches_the_most_volume_across_both_books() {
+        let mut yes = OrderBook::new(OptionType::Yes);
+        let mut no = OrderBook::new(OptionType::No);
+        yes.add_order(order(1, OptionType::Yes, OrderType::Buy, 6.0, 5));
+        yes.add_order(order(2, OptionType::Yes, OrderType::Sell, 5.0, 3));
+        // Buying No at 5 is selling Yes at 5
+        no.add_order(order(3, OptionType::No, OrderType::Buy, 5.0, 4));
+
+        let result = uncross(&yes, &no, Some(500)).unwrap();
+        assert_eq!((result.price_cents, result.volume), (500, 5));
+        let fills: Vec<(u64, u64, u32)> = result
+            .fills
+            .iter()
+            .map(|f| (f.buyer.id, f.seller.id, f.quantity))
+            .collect();
+        assert_eq!(fills, vec![(1, 2, 3), (1, 3, 2)]);
+
+        // Without a reference the middle of the tied prices wins
+        assert_eq!(uncross(&yes, &no, None).unwrap().price_cents, 600);
+        assert_eq!(execution_cents(&result.fills[1].seller, 600), 400);
+    }
+
+    #[test]
+    fn books_that_do_not_cross_have_no_uncross() {
+        let mut yes = OrderBook::new(OptionType::Yes);
+        let no = OrderBook::new(OptionType::No);
+        yes.add_order(order(1, OptionType::Yes, OrderType::Buy, 4.0, 5));
+        yes.add_order(order(2, OptionType::Yes, OrderType::Sell, 5.0, 5));
+        assert!(uncross(&yes, &no, None).is_none());
+    }
+}
@@ -1,15 +1,91 @@
+use crate::{
+    engine::ledger::Ledger,
+    types::ledger::{EntryReason, LedgerAccount, LedgerRef},
+};
 use std::collections::HashMap;
 use tokio::sync::RwLock;
 
+// Slack for float rounding when a batch is checked as a whole
+const EPSILON: f64 = 1e-9;
+
+/// One side of a fill settled as part of a batch by `BalanceManager::settle`.
+pub enum Leg {
+    /// Pays `amount` plus commission out of the `locked` its order reserved
+    /// for these shares; whatever `amount` leaves over is released.
+    Buy {
+        user_id: u32,
+        amount: f64,
+        locked: f64,
+        reference: LedgerRef,
+    },
+    Sell {
+        user_id: u32,
+        amount: f64,
+        reference: LedgerRef,
+    },
+}
+
+impl Leg {
+    pub fn user_id(&self) -> u32 {
+        match self {
+            Leg::Buy { user_id, .. } | Leg::Sell { user_id, .. } => *user_id,
+        }
+    }
+}
+
+/// Every mutation is journalled in `ledger`, so balances can be rebuilt and
+/// audited from it.
 pub struct BalanceManager {
     balances: RwLock<HashMap<u32, (f64, f64)>>, // (available, locked)
+    ledger: Ledger,
 }
 
 impl BalanceManager {
     pub fn new() -> Self {
         BalanceManager {
             balances: RwLock::new(HashMap::new()),
+            ledger: Ledger::new(),
+        }
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    pub fn ledger_mut(&mut self) -> &mut Ledger {
+        &mut self.ledger
+    }
+
+    pub async fn create_account(&self, user_id: u32) -> Result<(), String> {
+        let mut balances = self.balances.write().await;
+        if balances.contains_key(&user_id) {
+            return Err(format!("Account {} already exists", user_id));
         }
+        balances.insert(user_id, (0.0, 0.0));
+        Ok(())
+    }
+
+    /// Reopens `user_ids`, and any user with ledger postings, at the
+    /// balances their ledger accounts total to.
+    pub async fn restore(&self, user_ids: &[u32], ledger: HashMap<LedgerAccount, f64>) {
+        let mut balances = self.balances.write().await;
+        for &user_id in user_ids {
+            balances.entry(user_id).or_insert((0.0, 0.0));
+        }
+        for (&account, &amount) in &ledger {
+            match account {
+                LedgerAccount::Available(user_id) => {
+                    balances.entry(user_id).or_default().0 = amount
+                }
+                LedgerAccount::Locked(user_id) => balances.entry(user_id).or_default().1 = amount,
+                _ => {}
+            }
+        }
+        self.ledger.restore_totals(ledger).await;
+    }
+
+    pub async fn has_account(&self, user_id: u32) -> bool {
+        self.balances.read().await.contains_key(&user_id)
     }
 
     pub async fn check_balance(
@@ -19,7 +95,9 @@ impl BalanceManager {
         commission_rate: f64,
     ) -> Result<(), String> {
         let balances = self.balances.read().await;
-        let (available, _) = balances.get(&user_id).unwrap_or(&(1000.0, 0.0));
+        let (available, _) = balances
+            .get(&user_id)
+            .ok_or_else(|| Self::missing(user_id))?;
         let total_needed = amount * (1.0 + commission_rate);
         if *available >= total_needed {
             Ok(())
@@ -31,24 +109,56 @@ impl BalanceManager {
         }
     }
 
-    pub async fn lock_balance(&self, user_id: u32, amount: f64) -> Result<(), String> {
+    pub async fn lock_balance(
+        &self,
+        user_id: u32,
+        amount: f64,
+        reference: LedgerRef,
+    ) -> Result<(), String> {
         let mut balances = self.balances.write().await;
-        let (available, locked) = balances.entry(user_id).or_insert((1000.0, 0.0));
+        let (available, locked) = balances
+            .get_mut(&user_id)
+            .ok_or_else(|| Self::missing(user_id))?;
         if *available >= amount {
             *available -= amount;
             *locked += amount;
+            self.ledger
+                .post(
+                    EntryReason::Lock,
+                    reference,
+                    LedgerAccount::Available(user_id),
+                    LedgerAccount::Locked(user_id),
+                    amount,
+                )
+                .await;
             Ok(())
         } else {
             Err("Insufficient balance to lock".to_string())
         }
     }
 
-    pub async fn unlock_balance(&self, user_id: u32, amount: f64) -> Result<(), String> {
+    pub async fn unlock_balance(
+        &self,
+        user_id: u32,
+        amount: f64,
+        reference: LedgerRef,
+    ) -> Result<(), String> {
         let mut balances = self.balances.write().await;
-        let (available, locked) = balances.entry(user_id).or_insert((1000.0, 0.0));
+        let (available, locked) = balances
+            .get_mut(&user_id)
+            .ok_or_else(|| Self::missing(user_id))?;
         if *locked >= amount {
             *locked -= amount;
             *available += amount;
+            self.ledger
+                .post(
+                    EntryReason::Unlock,
+                    reference,
+                    LedgerAccount::Locked(user_id),
+                    LedgerAccount::Available(user_id),
+                    amount,
+                )
+                .await;
             Ok(())
         } else {
             Err("Insufficient locked balance to unlock".to_string())
@@ -60,14 +170,35 @@ impl BalanceManager {
         user_id: u32,
         amount: f64,
         commission_rate: f64,
+        reference: LedgerRef,
     ) -> Result<(), String> {
         let mut balances = self.balances.write().await;
-        let (available, locked) = balances.entry(user_id).or_insert((1000.0, 0.0));
+        let (available, locked) = balances
+            .get_mut(&user_id)
+            .ok_or_else(|| Self::missing(user_id))?;
         let total_deduction = amount * (1.0 + commission_rate);
         if *locked >= amount {
             *locked -= amount;
             if *available >= total_deduction - amount {
                 *available -= total_deduction - amount;
+                self.ledger
+                    .post(
+                        EntryReason::TradeDebit,
+                        reference.clone(),
+                        LedgerAccount::Locked(user_id),
+                        LedgerAccount::Clearing,
+                        amount,
+                    )
+                    .await;
+                self.ledger
+                    .post(
+                        EntryReason::Fee,
+                        reference,
+                        LedgerAccount::Available(user_id),
+                        LedgerAccount::Fees,
+                        total_deduction - amount,
+                    )
+                    .await;
                 Ok(())
             } else {
                 Err("Insufficient available balance for commission".to_string())
@@ -77,15 +208,194 @@ impl BalanceManager {
         }
     }
 
-    pub async fn credit_balance(&self, user_id: u32, amount: f64) -> Result<(), String> {
+    /// Pays `amount` out of clearing; negative amounts collect from the user.
+    pub async fn credit_balance(
+        &self,
+        user_id: u32,
+        amount: f64,
+        reason: EntryReason,
+        reference: LedgerRef,
+    ) -> Result<(), String> {
         let mut balances = self.balances.write().await;
-        let (available, _) = balances.entry(user_id).or_insert((1000.0, 0.0));
+        let (available, _) = balances
+            .get_mut(&user_id)
+            .ok_or_else(|| Self::missing(user_id))?;
         *available += amount;
+        self.ledger
+            .post(
+                reason,
+                reference,
+                LedgerAccount::Clearing,
+                LedgerAccount::Available(user_id),
+                amount,
+            )
+            .await;
+        Ok(())
+    }
+
+    /// Settles a batch of fills all together or not at all. Each buyer must
+    /// hold the locked funds and the commission for all their legs before
+    /// anything moves; if some fall short no balance changes and their ids
+    /// are returned.
+    pub async fn settle(&self, legs: &[Leg], commission_rate: f64) -> Result<(), Vec<u32>> {
+        let mut balances = self.balances.write().await;
+        // (available, locked) each user needs across the batch
+        let mut needed: HashMap<u32, (f64, f64)> = HashMap::new();
+        for leg in legs {
+            let need = needed.entry(leg.user_id()).or_default();
+            if let Leg::Buy { amount, locked, .. } = *leg {
+                need.0 += amount * commission_rate - (locked - amount);
+                need.1 += locked;
+            }
+        }
+        let mut short: Vec<u32> = needed
+            .iter()
+            .filter(|&(user_id, &(available, locked))| {
+                balances.get(user_id).is_none_or(|balance| {
+                    balance.0 < available - EPSILON || balance.1 < locked - EPSILON
+                })
+            })
+            .map(|(&user_id, _)| user_id)
+            .collect();
+        if !short.is_empty() {
+            short.sort_unstable();
+            return Err(short);
+        }
+
+        for leg in legs {
+            match leg {
+                Leg::Buy {
+                    user_id,
+                    amount,
+                    locked,
+                    reference,
+                } => {
+                    let user_id = *user_id;
+                    let (available, held) = balances.get_mut(&user_id).expect("checked above");
+                    let improvement = locked - amount;
+                    let fee = amount * commission_rate;
+                    *held -= locked;
+                    *available += improvement - fee;
+                    for (reason, from, to, value) in [
+                        (
+                            EntryReason::Unlock,
+                            LedgerAccount::Locked(user_id),
+                            LedgerAccount::Available(user_id),
+                            improvement,
+                        ),
+                        (
+                            EntryReason::TradeDebit,
+                            LedgerAccount::Locked(user_id),
+                            LedgerAccount::Clearing,
+                            *amount,
+                        ),
+                        (
+                            EntryReason::Fee,
+                            LedgerAccount::Available(user_id),
+                            LedgerAccount::Fees,
+                            fee,
+                        ),
+                    ] {
+                        self.ledger
+                            .post(reason, reference.clone(), from, to, value)
+                            .await;
+                    }
+                }
+                Leg::Sell {
+                    user_id,
+                    amount,
+                    reference,
+                } => {
+                    balances.get_mut(user_id).expect("checked above").0 += amount;
+                    self.ledger
+                        .post(
+                            EntryReason::TradeCredit,
+                            reference.clone(),
+                            LedgerAccount::Clearing,
+                            LedgerAccount::Available(*user_id),
+                            *amount,
+                        )
+                        .await;
+                }
+            }
+        }
         Ok(())
     }
 
+    pub async fn deposit(
+        &self,
+        user_id: u32,
+        amount: f64,
+        reference: LedgerRef,
+    ) -> Result<(f64, f64), String> {
+        Self::check_amount(amount)?;
+        let mut balances = self.balances.write().await;
+        let balance = balances
+            .get_mut(&user_id)
+            .ok_or_else(|| Self::missing(user_id))?;
+        balance.0 += amount;
+        self.ledger
+            .post(
+                EntryReason::Deposit,
+                reference,
+                LedgerAccount::External,
+                LedgerAccount::Available(user_id),
+                amount,
+            )
+            .await;
+        Ok(*balance)
+    }
+
+    /// Withdraws from available funds only; money locked by resting orders
+    /// stays put.
+    pub async fn withdraw(
+        &self,
+        user_id: u32,
+        amount: f64,
+        reference: LedgerRef,
+    ) -> Result<(f64, f64), String> {
+        Self::check_amount(amount)?;
+        let mut balances = self.balances.write().await;
+        let balance = balances
+            .get_mut(&user_id)
+            .ok_or_else(|| Self::missing(user_id))?;
+        if balance.0 < amount {
+            return Err(format!(
+                "Insufficient available balance: available {}, requested {}",
+                balance.0, amount
+            ));
+        }
+        balance.0 -= amount;
+        self.ledger
+            .post(
+                EntryReason::Withdrawal,
+                reference,
+                LedgerAccount::Available(user_id),
+                LedgerAccount::External,
+                amount,
+            )
+            .await;
+        Ok(*balance)
+    }
+
+    /// `(available, locked)`; zero for users without an account.
     pub async fn get_balance(&self, user_id: u32) -> (f64, f64) {
         let balances = self.balances.read().await;
-        *balances.get(&user_id).unwrap_or(&(1000.0, 0.0))
+        *balances.get(&user_id).unwrap_or(&(0.0, 0.0))
+    }
+
+    pub async fn all_balances(&self) -> HashMap<u32, (f64, f64)> {
+        self.balances.read().await.clone()
+    }
+
+    fn check_amount(amount: f64) -> Result<(), String> {
+        if !amount.is_finite() || amount <= 0.0 {
+            return Err("Amount must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    fn missing(user_id: u32) -> String {
+        format!("Account {} not found", user_id)
     }
 }
@@ -0,0 +1,93 @@
+use crate::types::{
+    market_data::{Candle, CandleInterval},
+    order::{OptionType, Trade},
+};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::RwLock;
+
+// Bars kept per (market, option, interval); older bars are dropped
+const MAX_CANDLES: usize = 1000;
+
+type SeriesKey = (String, OptionType, CandleInterval);
+
+pub struct CandleAggregator {
+    series: RwLock<HashMap<SeriesKey, VecDeque<Candle>>>,
+}
+
+impl CandleAggregator {
+    pub fn new() -> Self {
+        CandleAggregator {
+            series: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Folds trades into every interval and returns the latest state of each
+    /// bar that changed, one per (option, interval).
+    pub async fn apply_trades(&self, trades: &[Trade]) -> Vec<Candle> {
+        let mut series = self.series.write().await;
+        let mut touched: Vec<SeriesKey> = Vec::new();
+
+        for trade in trades {
+            for interval in CandleInterval::ALL {
+                let key = (trade.market_id.clone(), trade.option, interval);
+                let bars = series.entry(key.clone()).or_default();
+                let open_time = interval.open_time(trade.timestamp);
+
+                match bars.back_mut() {
+                    Some(bar) if bar.open_time == open_time => {
+                        bar.high = bar.high.max(trade.price);
+                        bar.low = bar.low.min(trade.price);
+                        bar.close = trade.price;
+                        bar.volume += trade.quantity as u64;
+                        bar.trade_count += 1;
+                    }
+                    _ => {
+                        bars.push_back(Candle {
+                            market_id: trade.market_id.clone(),
+                            option: trade.option,
+                            interval,
+                            open_time,
+                            open: trade.price,
+                            high: trade.price,
+                            low: trade.price,
+                            close: trade.price,
+                            volume: trade.quantity as u64,
+                            trade_count: 1,
+                        });
+                        if bars.len() > MAX_CANDLES {
+                            bars.pop_front();
+                        }
+                    }
+                }
+
+                if !touched.contains(&key) {
+                    touched.push(key);
+                }
+            }
+        }
+
+        touched
+            .iter()
+            .filter_map(|key| series.get(key).and_then(|bars| bars.back().cloned()))
+            .collect()
+    }
+
+    /// Returns up to `limit` of the most recent bars, oldest first.
+    pub async fn get_candles(
+        &self,
+        market_id: &str,
+        option: OptionType,
+        interval: CandleInterval,
+        limit: usize,
+    ) -> Vec<Candle> {
+        let series = self.series.read().await;
+        match series.get(&(market_id.to_string(), option, interval)) {
+            Some(bars) => bars
+                .iter()
+                .skip(bars.len().saturating_sub(limit))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::types::market::CircuitBreakerConfig;
+use std::collections::VecDeque;
+
+/// Tracks recent Yes-equivalent trade prices of one book and refuses fills
+/// that would move the price more than the configured limit inside the
+/// window.
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker {
+    pub config: CircuitBreakerConfig,
+    // (time in ms, Yes price), oldest first
+    history: VecDeque<(u64, f64)>,
+    // Set by a refused fill until the engine acts on it
+    tripped: bool,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            config,
+            history: VecDeque::new(),
+            tripped: false,
+        }
+    }
+
+    /// Checks a fill before it executes. A fill inside the band is recorded
+    /// and let through; one that would move the price too far trips the
+    /// breaker, and every fill is refused until `take_trip`. The window
+    /// restarts after a trip so the cooldown starts clean.
+    pub fn admit(&mut self, now_ms: u64, yes_price: f64) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+        if self.tripped {
+            return false;
+        }
+        let in_band = self
+            .band(now_ms)
+            .is_none_or(|(low, high)| (low..=high).contains(&yes_price));
+        if in_band {
+            self.history.push_back((now_ms, yes_price));
+        } else {
+            self.history.clear();
+            self.tripped = true;
+        }
+        in_band
+    }
+
+    /// The Yes prices a fill may trade at right now, `None` when nothing
+    /// limits them.
+    pub fn band(&mut self, now_ms: u64) -> Option<(f64, f64)> {
+        if !self.config.enabled {
+            return None;
+        }
+        let window_start = now_ms.saturating_sub(self.config.window_secs * 1000);
+        while self
+            .history
+            .front()
+            .is_some_and(|&(at_ms, _)| at_ms < window_start)
+        {
+            self.history.pop_front();
+        }
+
+        let prices = self.history.iter().map(|&(_, price)| price);
+        let low = prices.clone().reduce(f64::max)? - self.config.max_move;
+        let high = prices.reduce(f64::min)? + self.config.max_move;
+        Some((low, high))
+    }
+
+    /// True once for every trip.
+    pub fn take_trip(&mut self) -> bool {
+        std::mem::take(&mut self.tripped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(enabled: bool) -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerConfig {
+            enabled,
+            max_move: 1.0,
+            window_secs: 60,
+            ..CircuitBreakerConfig::default()
+        })
+    }
+
+    #[test]
+    fn band_follows_the_prices_inside_the_window() {
+        let mut breaker = breaker(true);
+        assert_eq!(breaker.band(0), None);
+        assert!(breaker.admit(0, 5.0));
+        assert!(breaker.admit(1_000, 5.5));
+        assert_eq!(breaker.band(2_000), Some((4.5, 6.0)));
+        // The first trade has aged out
+        assert_eq!(breaker.band(60_500), Some((4.5, 6.5)));
+        assert_eq!(breaker.band(62_000), None);
+    }
+
+    #[test]
+    fn a_fill_outside_the_band_trips_until_taken() {
+        let mut breaker = breaker(true);
+        assert!(breaker.admit(0, 5.0));
+        assert!(!breaker.admit(1_000, 6.5));
+        assert!(!breaker.admit(2_000, 5.0));
+        assert!(breaker.take_trip());
+        assert!(!breaker.take_trip());
+        // The window restarted with the trip
+        assert!(breaker.admit(3_000, 8.0));
+    }
+
+    #[test]
+    fn disabled_breaker_lets_everything_through() {
+        let mut breaker = breaker(false);
+        assert!(breaker.admit(0, 1.0));
+        assert!(breaker.admit(1_000, 9.0));
+        assert_eq!(breaker.band(1_000), None);
+        assert!(!breaker.take_trip());
+    }
+}
@@ -0,0 +1,42 @@
+use crate::types::{admin::AdminAction, risk::RejectCode};
+use std::collections::HashSet;
+use tokio::sync::RwLock;
+
+/// Engine-wide and per-user trading stops set by operators.
+pub struct KillSwitch {
+    halted: RwLock<bool>,
+    blocked_users: RwLock<HashSet<u32>>,
+}
+
+impl KillSwitch {
+    pub fn new() -> Self {
+        KillSwitch {
+            halted: RwLock::new(false),
+            blocked_users: RwLock::new(HashSet::new()),
+        }
+    }
+
+    pub async fn check(&self, user_id: u32) -> Result<(), RejectCode> {
+        if *self.halted.read().await {
+            return Err(RejectCode::TradingHalted);
+        }
+        if self.blocked_users.read().await.contains(&user_id) {
+            return Err(RejectCode::UserBlocked);
+        }
+        Ok(())
+    }
+
+    pub async fn apply(&self, action: &AdminAction) {
+        match action {
+            AdminAction::BlockUser { user_id, .. } => {
+                self.blocked_users.write().await.insert(*user_id);
+            }
+            AdminAction::UnblockUser { user_id } => {
+                self.blocked_users.write().await.remove(user_id);
+            }
+            AdminAction::HaltAll { .. } => *self.halted.write().await = true,
+            AdminAction::ResumeAll => *self.halted.write().await = false,
+            AdminAction::ResumeMarket { .. } => {}
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::types::ledger::{EntryReason, JournalEntry, LedgerAccount, LedgerRef, Posting};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::RwLock;
+
+// Saved entries kept in memory for ledger lookups; older ones live only in
+// the stored journal
+const KEPT_ENTRIES: usize = 10_000;
+
+/// Append-only double-entry journal of every balance movement. Only the
+/// latest entries stay in memory once they have been saved; the ones let go
+/// are summed into what the journal carries, so account balances are always
+/// replayed from entries rather than kept alongside them.
+pub struct Ledger {
+    journal: RwLock<Journal>,
+}
+
+struct Journal {
+    entries: VecDeque<JournalEntry>,
+    // Entries before this index have been pushed to the db queue
+    saved: usize,
+    next_id: u64,
+    // Stored totals at startup plus every entry dropped from memory since
+    carried: HashMap<LedgerAccount, f64>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Ledger {
+            journal: RwLock::new(Journal {
+                entries: VecDeque::new(),
+                saved: 0,
+                next_id: 1,
+                carried: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Numbers new entries after `last_id`, the highest already stored.
+    pub fn start_after(&mut self, last_id: u64) {
+        self.journal.get_mut().next_id = last_id + 1;
+    }
+
+    /// Takes up the account totals of a stored journal after a restart.
+    pub async fn restore_totals(&self, totals: HashMap<LedgerAccount, f64>) {
+        self.journal.write().await.carried = totals;
+    }
+
+    /// Records a movement of `amount` from `from` to `to`. Zero amounts are
+    /// not recorded.
+    pub async fn post(
+        &self,
+        reason: EntryReason,
+        reference: LedgerRef,
+        from: LedgerAccount,
+        to: LedgerAccount,
+        amount: f64,
+    ) {
+        if amount == 0.0 {
+            return;
+        }
+        let mut journal = self.journal.write().await;
+        let entry = JournalEntry {
+            id: journal.next_id,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64,
+            reason,
+            reference,
+            postings: vec![
+                Posting {
+                    account: from,
+                    amount: -amount,
+                },
+                Posting {
+                    account: to,
+                    amount,
+                },
+            ],
+        };
+        journal.next_id += 1;
+        journal.entries.push_back(entry);
+    }
+
+    /// Entries not yet pushed to the db queue, oldest first.
+    pub async fn unsaved(&self) -> Vec<JournalEntry> {
+        let journal = self.journal.read().await;
+        journal.entries.range(journal.saved..).cloned().collect()
+    }
+
+    /// Marks the oldest unsaved entry saved once its push has gone through,
+    /// and lets the oldest saved entries go past `KEPT_ENTRIES`.
+    pub async fn mark_saved(&self, id: u64) {
+        let mut journal = self.journal.write().await;
+        if journal
+            .entries
+            .get(journal.saved)
+            .is_some_and(|e| e.id == id)
+        {
+            journal.saved += 1;
+        }
+        while journal.entries.len() > KEPT_ENTRIES && journal.saved > 0 {
+            let Some(entry) = journal.entries.pop_front() else {
+                break;
+            };
+            for posting in &entry.postings {
+                *journal.carried.entry(posting.account).or_default() += posting.amount;
+            }
+            journal.saved -= 1;
+        }
+    }
+
+    /// The entries touching `user_id` still held in memory, oldest first.
+    pub async fn entries_for(&self, user_id: u32) -> Vec<JournalEntry> {
+        self.journal
+            .read()
+            .await
+            .entries
+            .iter()
+            .filter(|entry| entry.touches(user_id))
+            .cloned()
+            .collect()
+    }
+
+    /// Every account's balance replayed from the journal: what it carries
+    /// plus each entry still in memory.
+    pub async fn replay(&self) -> HashMap<LedgerAccount, f64> {
+        let journal = self.journal.read().await;
+        let mut balances = journal.carried.clone();
+        for posting in journal.entries.iter().flat_map(|entry| &entry.postings) {
+            *balances.entry(posting.account).or_default() += posting.amount;
+        }
+        balances
+    }
+
+    /// `(available, locked)` for `user_id`, replayed from the journal.
+    pub async fn rebuild_balance(&self, user_id: u32) -> (f64, f64) {
+        let balances = self.replay().await;
+        let total = |account| balances.get(&account).copied().unwrap_or(0.0);
+        (
+            total(LedgerAccount::Available(user_id)),
+            total(LedgerAccount::Locked(user_id)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn deposit(ledger: &Ledger, user_id: u32, amount: f64) {
+        ledger
+            .post(
+                EntryReason::Deposit,
+                LedgerRef::Transfer(1),
+                LedgerAccount::External,
+                LedgerAccount::Available(user_id),
+                amount,
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn entries_stay_unsaved_until_marked() {
+        let ledger = Ledger::new();
+        deposit(&ledger, 1, 10.0).await;
+        deposit(&ledger, 1, 5.0).await;
+
+        let unsaved = ledger.unsaved().await;
+        assert_eq!(unsaved.len(), 2);
+        // A failed push leaves the entry for the next flush
+        ledger.mark_saved(unsaved[0].id).await;
+        let unsaved = ledger.unsaved().await;
+        assert_eq!(unsaved.len(), 1);
+        assert_eq!(unsaved[0].id, 2);
+    }
+
+    #[tokio::test]
+    async fn totals_follow_postings() {
+        let mut ledger = Ledger::new();
+        ledger.start_after(41);
+        deposit(&ledger, 1, 10.0).await;
+        ledger
+            .post(
+                EntryReason::Lock,
+                LedgerRef::Order(1),
+                LedgerAccount::Available(1),
+                LedgerAccount::Locked(1),
+                4.0,
+            )
+            .await;
+
+        assert_eq!(ledger.rebuild_balance(1).await, (6.0, 4.0));
+        assert_eq!(ledger.replay().await[&LedgerAccount::External], -10.0);
+        assert_eq!(ledger.unsaved().await[0].id, 42);
+    }
+
+    #[tokio::test]
+    async fn replay_covers_entries_let_go() {
+        let ledger = Ledger::new();
+        ledger
+            .restore_totals(HashMap::from([
+                (LedgerAccount::External, -5.0),
+                (LedgerAccount::Available(1), 5.0),
+            ]))
+            .await;
+        for _ in 0..=KEPT_ENTRIES {
+            deposit(&ledger, 1, 1.0).await;
+        }
+        for entry in ledger.unsaved().await {
+            ledger.mark_saved(entry.id).await;
+        }
+
+        let expected = 5.0 + (KEPT_ENTRIES + 1) as f64;
+        assert_eq!(ledger.rebuild_balance(1).await, (expected, 0.0));
+        assert_eq!(ledger.replay().await[&LedgerAccount::External], -expected);
+    }
+}
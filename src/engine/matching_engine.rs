@@ -1,59 +1,247 @@
 use crate::{
-    engine::{balance_manager::BalanceManager, order_book::OrderBook},
+    engine::{
+        amm::{AMM_ORDER_ID, LmsrMarketMaker},
+        auction::{self, AuctionFill},
+        balance_manager::{BalanceManager, Leg},
+        candles::CandleAggregator,
+        circuit_breaker::CircuitBreaker,
+        kill_switch::KillSwitch,
+        order_book::{COMPLEMENT_CENTS, OrderBook, PriceLevels, SHARE_PAYOUT},
+        parimutuel::ParimutuelPool,
+        positions::PositionTracker,
+        reconciliation,
+        risk::{OrderExposure, RiskManager, RiskRejection, yes_direction},
+        scheduler::{MarketScheduler, ScheduledAction},
+        strategy::StrategyEvent,
+        ticker::{Quote, TickerTracker},
+    },
     redis::manager::RedisManager,
     types::{
+        account::{Balance, Position, Transfer, TransferKind},
+        admin::{AdminAction, AuditEntry},
         api::MessageToApi,
-        db::DbMessage,
-        market::Market,
+        db::{DbMessage, DbWrite, LastIds, StoredAccounts},
+        ledger::{EntryReason, LedgerAccount, LedgerAudit, LedgerRef},
+        market::{
+            BreakerAction, Market, MarketKind, MarketMetadata, MarketStatus, Resolution,
+            ScalarRange, TradingConfig, outcome_market_id,
+        },
+        market_data::{
+            AmmSnapshot, Candle, CandleInterval, CombinedBook, L3BookSnapshot, L3Event,
+            PoolSnapshot, Ticker,
+        },
         order::{OptionType, Order, OrderType, Trade},
+        reconciliation::Discrepancy,
+        risk::{RejectCode, RiskLimits, RiskScope},
         ws::WsMessage,
     },
 };
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use tokio::sync::RwLock;
 
+// How long a halted market collects orders before it reopens
+const REOPENING_AUCTION_MS: u64 = 30_000;
+
+// Market data captured from both books while the markets lock is held and
+// published once it is released
+struct BookUpdate {
+    l3_events: Vec<L3Event>,
+    yes_quote: Quote,
+    no_quote: Quote,
+    combined: CombinedBook,
+}
+
+impl BookUpdate {
+    fn capture(market_id: &str, yes_book: &mut OrderBook, no_book: &mut OrderBook) -> Self {
+        let mut l3_events = yes_book.take_l3_events();
+        l3_events.extend(no_book.take_l3_events());
+        BookUpdate {
+            l3_events,
+            yes_quote: (yes_book.best_bid(), yes_book.best_ask()),
+            no_quote: (no_book.best_bid(), no_book.best_ask()),
+            combined: OrderBook::get_combined_depth(market_id, yes_book, no_book),
+        }
+    }
+}
+
 pub struct MatchingEngine {
     markets: RwLock<HashMap<String, (OrderBook, OrderBook)>>,
+    // Every market ever created, keyed by the id it was created under
+    listings: RwLock<HashMap<String, Market>>,
+    scheduler: MarketScheduler,
+    breakers: RwLock<HashMap<String, CircuitBreaker>>,
+    risk: RiskManager,
+    kill_switch: KillSwitch,
+    audit: RwLock<Vec<AuditEntry>>,
+    next_audit_id: RwLock<u64>,
+    next_transfer_id: RwLock<u64>,
+    amms: RwLock<HashMap<String, LmsrMarketMaker>>,
+    pools: RwLock<HashMap<String, ParimutuelPool>>,
+    // Multi-outcome market id -> outcome labels, each with its own book pair
+    outcome_groups: RwLock<HashMap<String, Vec<String>>>,
+    scalar_ranges: RwLock<HashMap<String, ScalarRange>>,
+    // Resolutions started but not yet paid out in full
+    resolving: RwLock<HashMap<String, Resolution>>,
+    positions: PositionTracker,
     balances: BalanceManager,
+    candles: CandleAggregator,
+    ticker: TickerTracker,
     redis: RedisManager,
     next_order_id: RwLock<u64>,
     commission_rate: f64,
+    // Drained by the processor after every command and fed to strategies
+    strategy_events: RwLock<Vec<StrategyEvent>>,
+    // The last order refused by the risk layer, with its client_id; the
+    // processor answers it once the command has failed
+    rejection: RwLock<Option<(String, RiskRejection)>>,
 }
 
 impl MatchingEngine {
     pub fn new(redis: RedisManager) -> Self {
         MatchingEngine {
             markets: RwLock::new(HashMap::new()),
+            listings: RwLock::new(HashMap::new()),
+            scheduler: MarketScheduler::new(),
+            breakers: RwLock::new(HashMap::new()),
+            risk: RiskManager::new(),
+            kill_switch: KillSwitch::new(),
+            audit: RwLock::new(Vec::new()),
+            next_audit_id: RwLock::new(1),
+            next_transfer_id: RwLock::new(1),
+            amms: RwLock::new(HashMap::new()),
+            pools: RwLock::new(HashMap::new()),
+            outcome_groups: RwLock::new(HashMap::new()),
+            scalar_ranges: RwLock::new(HashMap::new()),
+            resolving: RwLock::new(HashMap::new()),
+            positions: PositionTracker::new(),
             balances: BalanceManager::new(),
+            candles: CandleAggregator::new(),
+            ticker: TickerTracker::new(),
             redis,
             next_order_id: RwLock::new(1),
             commission_rate: 0.0223,
+            strategy_events: RwLock::new(Vec::new()),
+            rejection: RwLock::new(None),
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn create_market(
         &self,
         market_id: String,
         question: String,
+        kind: MarketKind,
+        outcomes: Vec<String>,
+        range: Option<ScalarRange>,
+        metadata: MarketMetadata,
+        trading: TradingConfig,
         client_id: String,
     ) -> Result<(), String> {
         let mut markets = self.markets.write().await;
+        let mut pools = self.pools.write().await;
+        let mut outcome_groups = self.outcome_groups.write().await;
         println!("existing market: {:?}", markets);
-        if markets.contains_key(&market_id) {
+        if markets.contains_key(&market_id)
+            || pools.contains_key(&market_id)
+            || outcome_groups.contains_key(&market_id)
+        {
             return Err("Market already exists".to_string());
         }
-        markets.insert(
+        match (kind, range) {
+            (MarketKind::Scalar, Some(range)) => range.validate()?,
+            (MarketKind::Scalar, None) => return Err("Scalar markets need a range".to_string()),
+            (_, Some(_)) => return Err("Only scalar markets take a range".to_string()),
+            (_, None) => {}
+        }
+        if kind == MarketKind::Parimutuel && trading.opening_auction_secs.is_some() {
+            return Err("Pool markets have no auction".to_string());
+        }
+        let breaker = trading.circuit_breaker.unwrap_or_default();
+        breaker.validate()?;
+        let market = Market::new(
             market_id.clone(),
-            (
-                OrderBook::new(OptionType::Yes),
-                OrderBook::new(OptionType::No),
-            ),
+            question,
+            kind,
+            outcomes.clone(),
+            range,
+            metadata,
         );
-        let market = Market::new(market_id.clone(), question);
-        self.redis
-            .push_message("db_queue", &DbMessage::SaveMarket(market))
+        market.metadata.validate(market.created_at)?;
+
+        // Book pairs to open: the market itself, or one per outcome
+        let book_ids = match kind {
+            MarketKind::OrderBook | MarketKind::Scalar => vec![market_id.clone()],
+            MarketKind::MultiOutcome => {
+                let mut labels = outcomes.clone();
+                labels.sort();
+                labels.dedup();
+                if outcomes.len() < 2 || labels.len() != outcomes.len() {
+                    return Err(
+                        "Multi-outcome markets need at least two distinct outcomes".to_string()
+                    );
+                }
+                if outcomes.iter().any(|o| o.is_empty() || o.contains(':')) {
+                    return Err("Outcome labels must be non-empty and contain no ':'".to_string());
+                }
+                outcomes
+                    .iter()
+                    .map(|outcome| outcome_market_id(&market_id, outcome))
+                    .collect()
+            }
+            MarketKind::Parimutuel => {
+                if trading.amm_liquidity.is_some() {
+                    return Err("An AMM needs an order book market".to_string());
+                }
+                pools.insert(market_id.clone(), ParimutuelPool::new());
+                Vec::new()
+            }
+        };
+        if book_ids.iter().any(|id| markets.contains_key(id)) {
+            return Err("Market already exists".to_string());
+        }
+
+        let mut breakers = self.breakers.write().await;
+        for book_id in &book_ids {
+            breakers.insert(book_id.clone(), CircuitBreaker::new(breaker));
+            if let Some(b) = trading.amm_liquidity {
+                self.amms
+                    .write()
+                    .await
+                    .insert(book_id.clone(), LmsrMarketMaker::new(b)?);
+            }
+            markets.insert(
+                book_id.clone(),
+                (
+                    OrderBook::new(OptionType::Yes),
+                    OrderBook::new(OptionType::No),
+                ),
+            );
+        }
+        drop(breakers);
+        if kind == MarketKind::MultiOutcome {
+            outcome_groups.insert(market_id.clone(), outcomes);
+        }
+        if let Some(range) = range {
+            self.scalar_ranges
+                .write()
+                .await
+                .insert(market_id.clone(), range);
+        }
+        drop(pools);
+        drop(outcome_groups);
+        if let Some(close_time) = market.metadata.close_time {
+            self.scheduler
+                .schedule(&market_id, ScheduledAction::Close, close_time * 1000)
+                .await;
+        }
+        self.listings
+            .write()
             .await
-            .map_err(|e| e.to_string())?;
+            .insert(market_id.clone(), market.clone());
+        self.persist(DbMessage::SaveMarket(market)).await?;
+        if let Some(secs) = trading.opening_auction_secs {
+            self.start_auction(&market_id, secs * 1000).await?;
+        }
         self.redis
             .publish_message(
                 "responses",
@@ -67,6 +255,14 @@ impl MatchingEngine {
         Ok(())
     }
 
+    /// Carries every id sequence on from the highest ids already stored.
+    pub fn start_ids_after(&mut self, last: LastIds) {
+        *self.next_order_id.get_mut() = last.order + 1;
+        *self.next_transfer_id.get_mut() = last.transfer + 1;
+        *self.next_audit_id.get_mut() = last.audit + 1;
+        self.balances.ledger_mut().start_after(last.journal);
+    }
+
     async fn generate_order_id(&self) -> u64 {
         let mut id = self.next_order_id.write().await;
         let order_id = *id;
@@ -74,6 +270,7 @@ impl MatchingEngine {
         order_id
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn place_order(
         &self,
         user_id: u32,
@@ -84,19 +281,51 @@ impl MatchingEngine {
         quantity: u32,
         client_id: String,
     ) -> Result<(Order, Vec<Trade>), String> {
-        if price < 0.5 || price > 9.5 {
+        if !(0.5..=9.5).contains(&price) {
             return Err("Price must be between 0.5 and 9.5".to_string());
         }
+        let in_auction = self.trading_status(&market_id).await? == MarketStatus::Auction;
+        // Pool markets have no book; refuse them before anything is locked
+        if !self.markets.read().await.contains_key(&market_id) {
+            return Err("Market not found".to_string());
+        }
+        // Sellers lock nothing, but their proceeds need somewhere to go
+        if !self.balances.has_account(user_id).await {
+            return Err(format!("Account {} not found", user_id));
+        }
+        // A sell is covered by shares in hand that no other resting sell
+        // already offers; there is no collateral for a short
+        if order_type == OrderType::Sell {
+            let unoffered = self.unoffered_shares(user_id, &market_id, option).await;
+            if i64::from(quantity) > unoffered {
+                return Err(format!(
+                    "Not enough shares to sell: {} held and not already offered",
+                    unoffered.max(0)
+                ));
+            }
+        }
+        self.check_risk(
+            user_id,
+            &market_id,
+            option,
+            order_type.clone(),
+            price,
+            quantity,
+            &client_id,
+        )
+        .await?;
 
+        let order_id = self.generate_order_id().await;
         let amount = price * quantity as f64;
         if order_type == OrderType::Buy {
             self.balances
                 .check_balance(user_id, amount, self.commission_rate)
                 .await?;
-            self.balances.lock_balance(user_id, amount).await?;
+            self.balances
+                .lock_balance(user_id, amount, LedgerRef::Order(order_id))
+                .await?;
         }
 
-        let order_id = self.generate_order_id().await;
         println!("OrderId: {:?}", order_id);
         let mut order = Order::new(
             order_id,
@@ -110,24 +339,42 @@ impl MatchingEngine {
         println!("order: {:?}", order);
 
         // Acquire write lock and perform all operations within the scope
-        let (trades, bids, asks) = {
+        let (trades, bids, asks, book_update, tripped) = {
             let mut markets = self.markets.write().await;
             println!("Acquired markets lock in place_order");
             let (yes_book, no_book) = markets
                 .get_mut(&market_id)
                 .ok_or("Market not found".to_string())?;
 
-            // Running matching logic without moving yes_book and no_book
-            let trades = self
-                .match_order(
+            // Running matching logic without moving yes_book and no_book.
+            // During an auction orders only rest until the uncross
+            let mut trades = if in_auction {
+                Vec::new()
+            } else {
+                self.match_order(
                     &mut order,
                     &market_id,
                     yes_book, // Pass mutable reference without moving
                     no_book,  // Pass mutable reference without moving
                     client_id.clone(),
                 )
-                .await?;
+                .await?
+            };
             println!("matched_order: {:?}", trades);
+            // Matching stopped at the first fill the circuit breaker refused;
+            // the rest of the order rests for the halt or auction that follows
+            let mut tripped = self.take_breaker_trip(&market_id).await;
+
+            // Whatever the books could not fill is offered to the market's AMM
+            if order.quantity > 0
+                && !in_auction
+                && !tripped
+                && let Some(amm) = self.amms.write().await.get_mut(&market_id)
+            {
+                self.fill_with_amm(&mut order, amm, &mut trades, &client_id)
+                    .await?;
+                tripped = self.take_breaker_trip(&market_id).await;
+            }
             println!("remaining order quantity: {:?}", order.quantity);
 
             // Handle order placement based on option type
@@ -138,14 +385,6 @@ impl MatchingEngine {
                     } else {
                         yes_book.add_order(order.clone());
                     }
-
-                    // Save order if quantity remains
-                    if order.quantity > 0 {
-                        self.redis
-                            .push_message("db_queue", &DbMessage::SaveOrder(order.clone()))
-                            .await
-                            .map_err(|e| e.to_string())?;
-                    }
                     yes_book.get_depth()
                 }
                 OptionType::No => {
@@ -154,17 +393,12 @@ impl MatchingEngine {
                     } else {
                         no_book.add_order(order.clone());
                     }
-
-                    // Save order if quantity remains
-                    if order.quantity > 0 {
-                        self.redis
-                            .push_message("db_queue", &DbMessage::SaveOrder(order.clone()))
-                            .await
-                            .map_err(|e| e.to_string())?;
-                    }
                     no_book.get_depth()
                 }
             };
+            self.save_order(&order).await?;
+
+            let book_update = BookUpdate::capture(&market_id, yes_book, no_book);
 
             println!(
                 "Placed order: {:?}",
@@ -174,7 +408,7 @@ impl MatchingEngine {
                 }
             );
 
-            (trades, bids, asks)
+            (trades, bids, asks, book_update, tripped)
         };
 
         // Publish order placement response
@@ -202,9 +436,105 @@ impl MatchingEngine {
             .await
             .map_err(|e| e.to_string())?;
 
+        self.publish_book_update(&market_id, &trades, book_update)
+            .await?;
+        if tripped {
+            self.trip_breaker(&market_id).await?;
+        }
+
         Ok((order, trades))
     }
 
+    // Trades go out on their market's own channel. The fill has already
+    // happened by now, so a failed publish is logged rather than allowed to
+    // abort the rest of the match
+    async fn publish_trade(&self, trade: &Trade) {
+        if let Err(e) = self
+            .redis
+            .publish_message(
+                &format!("market_updates_{}", trade.market_id),
+                &WsMessage::Trade {
+                    trade: trade.clone(),
+                },
+            )
+            .await
+        {
+            tracing::error!("Failed to publish trade on {}: {}", trade.market_id, e);
+        }
+    }
+
+    /// Publishes the market data derived from one engine event: order-level
+    /// changes, candles, ticker and the combined book.
+    async fn publish_book_update(
+        &self,
+        market_id: &str,
+        trades: &[Trade],
+        update: BookUpdate,
+    ) -> Result<(), String> {
+        if !update.l3_events.is_empty() {
+            self.redis
+                .publish_message(
+                    "market_updates",
+                    &WsMessage::L3Update {
+                        market_id: market_id.to_string(),
+                        events: update.l3_events,
+                    },
+                )
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        // Roll the fills into OHLCV bars and push every bar that changed
+        for candle in self.candles.apply_trades(trades).await {
+            self.redis
+                .publish_message("market_updates", &WsMessage::Candle { candle })
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        let ticker = self
+            .ticker
+            .update(market_id, trades, update.yes_quote, update.no_quote)
+            .await;
+        self.redis
+            .publish_message("market_updates", &WsMessage::Ticker { ticker })
+            .await
+            .map_err(|e| e.to_string())?;
+
+        self.redis
+            .publish_message(
+                "market_updates",
+                &WsMessage::CombinedDepth {
+                    book: update.combined.clone(),
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let mut strategy_events = self.strategy_events.write().await;
+        strategy_events.extend(trades.iter().cloned().map(StrategyEvent::Trade));
+        strategy_events.push(StrategyEvent::Book {
+            market_id: market_id.to_string(),
+            book: update.combined,
+        });
+
+        Ok(())
+    }
+
+    pub async fn take_strategy_events(&self) -> Vec<StrategyEvent> {
+        std::mem::take(&mut *self.strategy_events.write().await)
+    }
+
+    /// The risk rejection behind the failure of the command sent as
+    /// `client_id`, if that is why it failed.
+    pub async fn take_rejection(&self, client_id: &str) -> Option<RiskRejection> {
+        let mut rejection = self.rejection.write().await;
+        match rejection.take() {
+            Some((id, rejection)) if id == client_id => Some(rejection),
+            _ => None,
+        }
+    }
+
     async fn match_order(
         &self,
         order: &mut Order,
@@ -233,17 +563,6 @@ impl MatchingEngine {
 
         let counter_price = 10.0 - order.price;
 
-        remaining_quantity = self
-            .match_with_counter_book(
-                counter_book,
-                order,
-                remaining_quantity,
-                counter_price,
-                &mut trades,
-                &client_id,
-            )
-            .await?;
-
         remaining_quantity = self
             .match_with_counter_book_same_type(
                 counter_book,
@@ -255,7 +574,11 @@ impl MatchingEngine {
             )
             .await?;
 
-        order.quantity = remaining_quantity;
+        // Every trade here is one of this order's own fills
+        for trade in &trades {
+            order.fill(trade.quantity, trade.price);
+        }
+        debug_assert_eq!(order.quantity, remaining_quantity);
 
         if !trades.is_empty() {
             let last_price = trades.last().unwrap().price;
@@ -275,6 +598,217 @@ impl MatchingEngine {
         Ok(trades)
     }
 
+    // Shares `user_id` holds in one side of a book less what their resting
+    // sells there already offer
+    async fn unoffered_shares(&self, user_id: u32, market_id: &str, option: OptionType) -> i64 {
+        let offered: i64 = self
+            .markets
+            .read()
+            .await
+            .get(market_id)
+            .map(|(yes_book, no_book)| match option {
+                OptionType::Yes => yes_book,
+                OptionType::No => no_book,
+            })
+            .into_iter()
+            .flat_map(|book| book.get_open_orders(user_id))
+            .filter(|order| order.order_type == OrderType::Sell)
+            .map(|order| i64::from(order.quantity))
+            .sum();
+        self.positions.get(user_id, market_id, option).await - offered
+    }
+
+    // Each side of a fill gains shares of its own book's option if its order
+    // was a buy and gives them up if it was a sell, at the price its cash moved
+    async fn record_positions(&self, orders: [&Order; 2], quantity: u32, price: f64) {
+        for order in orders {
+            self.record_position(order, quantity, price).await;
+        }
+    }
+
+    // Buyers pay commission on top of the price; sellers are credited in full
+    async fn record_position(&self, order: &Order, quantity: u32, price: f64) {
+        let (delta, fee) = match order.order_type {
+            OrderType::Buy => (
+                quantity as i64,
+                price * quantity as f64 * self.commission_rate,
+            ),
+            OrderType::Sell => (-(quantity as i64), 0.0),
+        };
+        self.positions
+            .record_fill(
+                order.user_id,
+                &order.market_id,
+                order.option,
+                delta,
+                price,
+                fee,
+            )
+            .await;
+    }
+
+    // A buy locked its limit price; release what it saved by executing at
+    // `price` instead, or the difference stays locked for good
+    async fn release_improvement(
+        &self,
+        order: &Order,
+        price: f64,
+        quantity: u32,
+        reference: LedgerRef,
+    ) -> Result<(), String> {
+        let improvement = (order.price - price) * quantity as f64;
+        if improvement > 0.0 {
+            self.balances
+                .unlock_balance(order.user_id, improvement, reference)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn fill_with_amm(
+        &self,
+        order: &mut Order,
+        amm: &mut LmsrMarketMaker,
+        trades: &mut Vec<Trade>,
+        client_id: &str,
+    ) -> Result<(), String> {
+        // The AMM walks its price only as far as the circuit breaker allows
+        let limit = match (
+            &order.order_type,
+            self.breaker_band(&order.market_id, order.option).await,
+        ) {
+            (OrderType::Buy, Some((_, high))) => order.price.min(high),
+            (OrderType::Sell, Some((low, _))) => order.price.max(low),
+            (_, None) => order.price,
+        };
+        let quantity = match order.order_type {
+            OrderType::Buy => amm.max_buy(order.option, limit),
+            // The AMM only buys back shares the seller holds
+            OrderType::Sell => {
+                let held = self
+                    .positions
+                    .get(order.user_id, &order.market_id, order.option)
+                    .await;
+                amm.max_sell(order.option, limit)
+                    .min(u32::try_from(held.max(0)).unwrap_or(u32::MAX))
+            }
+        }
+        .min(order.quantity);
+        if quantity == 0 {
+            return Ok(());
+        }
+        // The limit holds the AMM's marginal price inside the band, but an
+        // AMM quoting past the band's far edge still fills at an average
+        // outside it, so the fill goes to the breaker like any other first
+        let quoted = match order.order_type {
+            OrderType::Buy => amm.buy_cost(order.option, quantity),
+            OrderType::Sell => amm.sell_proceeds(order.option, quantity),
+        };
+        let quoted_price = (quoted / quantity as f64 * 100.0).round() / 100.0;
+        if !self
+            .admit_fill(&order.market_id, order.option, quoted_price)
+            .await
+        {
+            return Ok(());
+        }
+
+        // The house puts up the rest of each share's collateral when its AMM
+        // sells and takes it back when the AMM buys
+        let collateral = SHARE_PAYOUT * quantity as f64;
+        let (buy_order_id, sell_order_id, amount) = match order.order_type {
+            OrderType::Buy => {
+                let cost = amm.buy(order.option, quantity);
+                let reference = LedgerRef::Trade {
+                    buy_order_id: order.id,
+                    sell_order_id: AMM_ORDER_ID,
+                };
+                self.balances
+                    .deduct_balance(order.user_id, cost, self.commission_rate, reference.clone())
+                    .await?;
+                self.balances
+                    .ledger()
+                    .post(
+                        EntryReason::TradeDebit,
+                        reference.clone(),
+                        LedgerAccount::House,
+                        LedgerAccount::Clearing,
+                        collateral - cost,
+                    )
+                    .await;
+                // The order locked its limit price; release what the AMM improved on
+                let improvement = (order.price * quantity as f64 - cost).max(0.0);
+                self.balances
+                    .unlock_balance(order.user_id, improvement, reference)
+                    .await?;
+                (order.id, AMM_ORDER_ID, cost)
+            }
+            OrderType::Sell => {
+                let proceeds = amm.sell(order.option, quantity);
+                let reference = LedgerRef::Trade {
+                    buy_order_id: AMM_ORDER_ID,
+                    sell_order_id: order.id,
+                };
+                self.balances
+                    .credit_balance(
+                        order.user_id,
+                        proceeds,
+                        EntryReason::TradeCredit,
+                        reference.clone(),
+                    )
+                    .await?;
+                self.balances
+                    .ledger()
+                    .post(
+                        EntryReason::TradeCredit,
+                        reference,
+                        LedgerAccount::Clearing,
+                        LedgerAccount::House,
+                        collateral - proceeds,
+                    )
+                    .await;
+                (AMM_ORDER_ID, order.id, proceeds)
+            }
+        };
+
+        let trade = Trade {
+            buy_order_id,
+            sell_order_id,
+            market_id: order.market_id.clone(),
+            option: order.option,
+            price: (amount / quantity as f64 * 100.0).round() / 100.0,
+            quantity,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        };
+        trades.push(trade.clone());
+        order.fill(quantity, amount / quantity as f64);
+        // The AMM's own inventory lives in its q_yes / q_no
+        self.record_position(order, quantity, amount / quantity as f64)
+            .await;
+
+        self.persist(DbMessage::UpdateBalance {
+            user_id: order.user_id,
+            balance: self.balances.get_balance(order.user_id).await.0,
+        })
+        .await?;
+        self.persist(DbMessage::SaveTrade(trade.clone())).await?;
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::OrderMatched {
+                    trade: trade.clone(),
+                    client_id: client_id.to_string(),
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        self.publish_trade(&trade).await;
+
+        Ok(())
+    }
+
     async fn match_with_book(
         &self,
         book: &mut OrderBook,
@@ -296,6 +830,13 @@ impl MatchingEngine {
                         );
 
                         if ask_price <= order.price {
+                            // Stop at the first fill outside the circuit breaker's band
+                            if !self
+                                .admit_fill(&order.market_id, order.option, ask_price)
+                                .await
+                            {
+                                break;
+                            }
                             if let Some(ask) = asks.pop_front() {
                                 let matched_quantity = remaining_quantity.min(ask.quantity);
                                 println!("BUY: matched_quantity={}", matched_quantity);
@@ -313,12 +854,33 @@ impl MatchingEngine {
                                         .as_secs(),
                                 };
                                 trades.push(trade.clone());
+                                self.record_positions([&*order, &ask], matched_quantity, ask_price)
+                                    .await;
 
                                 let amount = ask_price * matched_quantity as f64;
                                 self.balances
-                                    .deduct_balance(order.user_id, amount, self.commission_rate)
+                                    .deduct_balance(
+                                        order.user_id,
+                                        amount,
+                                        self.commission_rate,
+                                        LedgerRef::trade(&trade),
+                                    )
+                                    .await?;
+                                self.release_improvement(
+                                    order,
+                                    ask_price,
+                                    matched_quantity,
+                                    LedgerRef::trade(&trade),
+                                )
+                                .await?;
+                                self.balances
+                                    .credit_balance(
+                                        ask.user_id,
+                                        amount,
+                                        EntryReason::TradeCredit,
+                                        LedgerRef::trade(&trade),
+                                    )
                                     .await?;
-                                self.balances.credit_balance(ask.user_id, amount).await?;
 
                                 // redis_messages.push(DbMessage::UpdateBalance {
                                 //     user_id: order.user_id,
@@ -334,34 +896,17 @@ impl MatchingEngine {
                                 //     client_id: client_id.to_string(),
                                 // });
 
-                                self.redis
-                                    .push_message(
-                                        "db_queue",
-                                        &DbMessage::UpdateBalance {
-                                            user_id: order.user_id,
-                                            balance: self
-                                                .balances
-                                                .get_balance(order.user_id)
-                                                .await
-                                                .0,
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-                                self.redis
-                                    .push_message(
-                                        "db_queue",
-                                        &DbMessage::UpdateBalance {
-                                            user_id: ask.user_id,
-                                            balance: self.balances.get_balance(ask.user_id).await.0,
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-                                self.redis
-                                    .push_message("db_queue", &DbMessage::SaveTrade(trade.clone()))
-                                    .await
-                                    .map_err(|e| e.to_string())?;
+                                self.persist(DbMessage::UpdateBalance {
+                                    user_id: order.user_id,
+                                    balance: self.balances.get_balance(order.user_id).await.0,
+                                })
+                                .await?;
+                                self.persist(DbMessage::UpdateBalance {
+                                    user_id: ask.user_id,
+                                    balance: self.balances.get_balance(ask.user_id).await.0,
+                                })
+                                .await?;
+                                self.persist(DbMessage::SaveTrade(trade.clone())).await?;
 
                                 self.redis
                                     .publish_message(
@@ -374,25 +919,19 @@ impl MatchingEngine {
                                     .await
                                     .map_err(|e| e.to_string())?;
 
-                                self.redis
-                                    .publish_message(
-                                        &format!("market_updates_{}", order.market_id),
-                                        &WsMessage::Trade {
-                                            trade: trade.clone(),
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string());
+                                self.publish_trade(&trade).await;
 
                                 remaining_quantity -= matched_quantity;
-                                if ask.quantity > matched_quantity {
-                                    let mut new_ask = ask.clone();
-                                    new_ask.quantity -= matched_quantity;
-                                    asks.push_front(new_ask);
+                                let mut filled = ask.clone();
+                                filled.fill(matched_quantity, trade.price);
+                                self.save_order(&filled).await?;
+                                if filled.quantity > 0 {
+                                    asks.push_front(filled);
                                 }
                                 if asks.is_empty() {
                                     book.asks.remove(&ask_price_cents);
                                 }
+                                book.record_fill(&ask, matched_quantity);
                             }
                         } else {
                             println!(
@@ -409,7 +948,7 @@ impl MatchingEngine {
             }
             OrderType::Sell => {
                 while remaining_quantity > 0 {
-                    if let Some((&bid_price_cents, bids)) = book.bids.iter_mut().rev().next() {
+                    if let Some((&bid_price_cents, bids)) = book.bids.iter_mut().next_back() {
                         let bid_price = bid_price_cents as f64 / 100.0;
                         println!(
                             "Sell: checking bid_price={} vs order_price={}",
@@ -417,6 +956,13 @@ impl MatchingEngine {
                         );
 
                         if bid_price >= order.price {
+                            // Stop at the first fill outside the circuit breaker's band
+                            if !self
+                                .admit_fill(&order.market_id, order.option, bid_price)
+                                .await
+                            {
+                                break;
+                            }
                             if let Some(bid) = bids.pop_front() {
                                 let matched_quantity = remaining_quantity.min(bid.quantity);
                                 println!("Sell: matched_quantity={}", matched_quantity);
@@ -434,12 +980,26 @@ impl MatchingEngine {
                                         .as_secs(),
                                 };
                                 trades.push(trade.clone());
+                                self.record_positions([&*order, &bid], matched_quantity, bid_price)
+                                    .await;
 
                                 let amount = bid_price * matched_quantity as f64;
                                 self.balances
-                                    .deduct_balance(bid.user_id, amount, self.commission_rate)
+                                    .deduct_balance(
+                                        bid.user_id,
+                                        amount,
+                                        self.commission_rate,
+                                        LedgerRef::trade(&trade),
+                                    )
+                                    .await?;
+                                self.balances
+                                    .credit_balance(
+                                        order.user_id,
+                                        amount,
+                                        EntryReason::TradeCredit,
+                                        LedgerRef::trade(&trade),
+                                    )
                                     .await?;
-                                self.balances.credit_balance(order.user_id, amount).await?;
 
                                 // redis_messages.push(DbMessage::UpdateBalance {
                                 //     user_id: bid.user_id,
@@ -455,34 +1015,17 @@ impl MatchingEngine {
                                 //     client_id: client_id.to_string(),
                                 // });
 
-                                self.redis
-                                    .push_message(
-                                        "db_queue",
-                                        &DbMessage::UpdateBalance {
-                                            user_id: bid.user_id,
-                                            balance: self.balances.get_balance(bid.user_id).await.0,
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-                                self.redis
-                                    .push_message(
-                                        "db_queue",
-                                        &DbMessage::UpdateBalance {
-                                            user_id: order.user_id,
-                                            balance: self
-                                                .balances
-                                                .get_balance(order.user_id)
-                                                .await
-                                                .0,
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-                                self.redis
-                                    .push_message("db_queue", &DbMessage::SaveTrade(trade.clone()))
-                                    .await
-                                    .map_err(|e| e.to_string())?;
+                                self.persist(DbMessage::UpdateBalance {
+                                    user_id: bid.user_id,
+                                    balance: self.balances.get_balance(bid.user_id).await.0,
+                                })
+                                .await?;
+                                self.persist(DbMessage::UpdateBalance {
+                                    user_id: order.user_id,
+                                    balance: self.balances.get_balance(order.user_id).await.0,
+                                })
+                                .await?;
+                                self.persist(DbMessage::SaveTrade(trade.clone())).await?;
 
                                 self.redis
                                     .publish_message(
@@ -495,25 +1038,19 @@ impl MatchingEngine {
                                     .await
                                     .map_err(|e| e.to_string())?;
 
-                                self.redis
-                                    .publish_message(
-                                        &format!("market_updates_{}", order.market_id),
-                                        &WsMessage::Trade {
-                                            trade: trade.clone(),
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string());
+                                self.publish_trade(&trade).await;
 
                                 remaining_quantity -= matched_quantity;
-                                if bid.quantity > matched_quantity {
-                                    let mut new_bid = bid.clone();
-                                    new_bid.quantity -= matched_quantity;
-                                    bids.push_front(new_bid);
+                                let mut filled = bid.clone();
+                                filled.fill(matched_quantity, trade.price);
+                                self.save_order(&filled).await?;
+                                if filled.quantity > 0 {
+                                    bids.push_front(filled);
                                 }
                                 if bids.is_empty() {
                                     book.bids.remove(&bid_price_cents);
                                 }
+                                book.record_fill(&bid, matched_quantity);
                             }
                         } else {
                             println!(
@@ -559,7 +1096,14 @@ impl MatchingEngine {
         Ok(remaining_quantity)
     }
 
-    async fn match_with_counter_book(
+    // Pairs the order with the opposite option's resting orders on the same
+    // side. A Yes buy and a No buy whose prices add up to at least a share's
+    // payout mint a Yes + No pair, each paying its side into clearing; two
+    // sells adding up to at most the payout burn one, each credited its side
+    // out of clearing. The resting order trades at its own price and the
+    // incoming one at the complement, so any surplus improves the incoming
+    // order. A No ask is an implied Yes bid, so a Yes buy never crosses it
+    async fn match_with_counter_book_same_type(
         &self,
         counter_book: &mut OrderBook,
         order: &mut Order,
@@ -568,360 +1112,148 @@ impl MatchingEngine {
         trades: &mut Vec<Trade>,
         client_id: &str,
     ) -> Result<u32, String> {
-        match order.order_type {
-            OrderType::Buy => {
-                while remaining_quantity > 0 {
-                    if let Some((&ask_price_cents, asks)) = counter_book.asks.iter_mut().next() {
-                        let ask_price = ask_price_cents as f64 / 100.0;
-                        if ask_price <= counter_price {
-                            if let Some(ask) = asks.pop_front() {
-                                let matched_quantity = remaining_quantity.min(ask.quantity);
-                                let trade = Trade {
-                                    buy_order_id: order.id,
-                                    sell_order_id: ask.id,
-                                    market_id: order.market_id.clone(),
-                                    option: order.option,
-                                    price: order.price,
-                                    quantity: matched_quantity,
-                                    timestamp: std::time::SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .unwrap()
-                                        .as_secs(),
-                                };
-                                trades.push(trade.clone());
+        let counter_cents = OrderBook::price_to_cents(counter_price);
+        while remaining_quantity > 0 {
+            let resting = match order.order_type {
+                OrderType::Buy => counter_book
+                    .bids
+                    .iter()
+                    .next_back()
+                    .filter(|&(&price_cents, _)| price_cents >= counter_cents),
+                OrderType::Sell => counter_book
+                    .asks
+                    .iter()
+                    .next()
+                    .filter(|&(&price_cents, _)| price_cents <= counter_cents),
+            }
+            .and_then(|(_, queue)| queue.front().cloned());
+            let Some(resting) = resting else {
+                break;
+            };
 
-                                let amount = order.price * matched_quantity as f64;
-                                self.balances
-                                    .deduct_balance(order.user_id, amount, self.commission_rate)
-                                    .await?;
-                                self.balances.credit_balance(ask.user_id, amount).await?;
-                                self.redis
-                                    .push_message(
-                                        "db_queue",
-                                        &DbMessage::UpdateBalance {
-                                            user_id: order.user_id,
-                                            balance: self
-                                                .balances
-                                                .get_balance(order.user_id)
-                                                .await
-                                                .0,
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-                                self.redis
-                                    .push_message(
-                                        "db_queue",
-                                        &DbMessage::UpdateBalance {
-                                            user_id: ask.user_id,
-                                            balance: self.balances.get_balance(ask.user_id).await.0,
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-                                self.redis
-                                    .push_message("db_queue", &DbMessage::SaveTrade(trade.clone()))
-                                    .await
-                                    .map_err(|e| e.to_string())?;
+            let mut matched_quantity = remaining_quantity.min(resting.quantity);
+            // Two sells only burn shares both sellers still hold; a resting
+            // sell left without any comes off the book
+            if order.order_type == OrderType::Sell {
+                let own = self
+                    .positions
+                    .get(order.user_id, &order.market_id, order.option)
+                    .await;
+                let theirs = self
+                    .positions
+                    .get(resting.user_id, &resting.market_id, resting.option)
+                    .await;
+                let (own, theirs) = (
+                    u32::try_from(own).unwrap_or(0),
+                    u32::try_from(theirs).unwrap_or(0),
+                );
+                if own == 0 {
+                    break;
+                }
+                if theirs == 0 {
+                    if let Some(mut stale) =
+                        counter_book.remove_order(OrderType::Sell, resting.price, resting.id)
+                    {
+                        stale.cancel();
+                        self.save_order(&stale).await?;
+                    }
+                    continue;
+                }
+                matched_quantity = matched_quantity.min(own).min(theirs);
+            }
 
-                                self.redis
-                                    .publish_message(
-                                        "responses",
-                                        &MessageToApi::OrderMatched {
-                                            trade: trade.clone(),
-                                            client_id: client_id.to_string(),
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-
-                                remaining_quantity -= matched_quantity;
-                                if ask.quantity > matched_quantity {
-                                    let mut new_ask = ask.clone();
-                                    new_ask.quantity -= matched_quantity;
-                                    asks.push_front(new_ask);
-                                }
-                                if asks.is_empty() {
-                                    counter_book.asks.remove(&ask_price_cents);
-                                }
-                            }
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
-                }
+            let price = SHARE_PAYOUT - resting.price;
+            if !self.admit_fill(&order.market_id, order.option, price).await {
+                break;
             }
-            OrderType::Sell => {
-                while remaining_quantity > 0 {
-                    if let Some((&bid_price_cents, bids)) =
-                        counter_book.bids.iter_mut().rev().next()
-                    {
-                        let bid_price = bid_price_cents as f64 / 100.0;
-                        if bid_price >= counter_price {
-                            if let Some(bid) = bids.pop_front() {
-                                let matched_quantity = remaining_quantity.min(bid.quantity);
-                                let trade = Trade {
-                                    buy_order_id: bid.id,
-                                    sell_order_id: order.id,
-                                    market_id: order.market_id.clone(),
-                                    option: order.option,
-                                    price: order.price,
-                                    quantity: matched_quantity,
-                                    timestamp: std::time::SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .unwrap()
-                                        .as_secs(),
-                                };
-                                trades.push(trade.clone());
-
-                                let amount = order.price * matched_quantity as f64;
-                                self.balances
-                                    .deduct_balance(bid.user_id, amount, self.commission_rate)
-                                    .await?;
-                                self.balances.credit_balance(order.user_id, amount).await?;
-                                self.redis
-                                    .push_message(
-                                        "db_queue",
-                                        &DbMessage::UpdateBalance {
-                                            user_id: bid.user_id,
-                                            balance: self.balances.get_balance(bid.user_id).await.0,
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-                                self.redis
-                                    .push_message(
-                                        "db_queue",
-                                        &DbMessage::UpdateBalance {
-                                            user_id: order.user_id,
-                                            balance: self
-                                                .balances
-                                                .get_balance(order.user_id)
-                                                .await
-                                                .0,
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-                                self.redis
-                                    .push_message("db_queue", &DbMessage::SaveTrade(trade.clone()))
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-
-                                self.redis
-                                    .publish_message(
-                                        "responses",
-                                        &MessageToApi::OrderMatched {
-                                            trade: trade.clone(),
-                                            client_id: client_id.to_string(),
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-
-                                remaining_quantity -= matched_quantity;
-                                if bid.quantity > matched_quantity {
-                                    let mut new_bid = bid.clone();
-                                    new_bid.quantity -= matched_quantity;
-                                    bids.push_front(new_bid);
-                                }
-                                if bids.is_empty() {
-                                    counter_book.bids.remove(&bid_price_cents);
-                                }
-                            }
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
+            let (buy_order_id, sell_order_id) = match order.order_type {
+                OrderType::Buy => (order.id, resting.id),
+                OrderType::Sell => (resting.id, order.id),
+            };
+            let trade = Trade {
+                buy_order_id,
+                sell_order_id,
+                market_id: order.market_id.clone(),
+                option: order.option,
+                price,
+                quantity: matched_quantity,
+                timestamp: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+            };
+            let reference = LedgerRef::trade(&trade);
+            match order.order_type {
+                OrderType::Buy => {
+                    self.balances
+                        .deduct_balance(
+                            resting.user_id,
+                            resting.price * matched_quantity as f64,
+                            self.commission_rate,
+                            reference.clone(),
+                        )
+                        .await?;
+                    self.balances
+                        .deduct_balance(
+                            order.user_id,
+                            price * matched_quantity as f64,
+                            self.commission_rate,
+                            reference.clone(),
+                        )
+                        .await?;
+                    self.release_improvement(order, price, matched_quantity, reference)
+                        .await?;
                 }
-            }
-        }
-        Ok(remaining_quantity)
-    }
-
-    async fn match_with_counter_book_same_type(
-        &self,
-        counter_book: &mut OrderBook,
-        order: &mut Order,
-        mut remaining_quantity: u32,
-        counter_price: f64,
-        trades: &mut Vec<Trade>,
-        client_id: &str,
-    ) -> Result<u32, String> {
-        match order.order_type {
-            OrderType::Buy => {
-                while remaining_quantity > 0 {
-                    if let Some((&bid_price_cents, bids)) =
-                        counter_book.bids.iter_mut().rev().next()
-                    {
-                        let bid_price = bid_price_cents as f64 / 100.0;
-                        if bid_price >= counter_price {
-                            if let Some(bid) = bids.pop_front() {
-                                let matched_quantity = remaining_quantity.min(bid.quantity);
-                                let trade = Trade {
-                                    buy_order_id: order.id,
-                                    sell_order_id: bid.id,
-                                    market_id: order.market_id.clone(),
-                                    option: order.option,
-                                    price: order.price,
-                                    quantity: matched_quantity,
-                                    timestamp: std::time::SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .unwrap()
-                                        .as_secs(),
-                                };
-                                trades.push(trade.clone());
-
-                                let amount = order.price * matched_quantity as f64;
-                                self.balances
-                                    .deduct_balance(order.user_id, amount, self.commission_rate)
-                                    .await?;
-                                self.balances.credit_balance(bid.user_id, amount).await?;
-                                self.redis
-                                    .push_message(
-                                        "db_queue",
-                                        &DbMessage::UpdateBalance {
-                                            user_id: order.user_id,
-                                            balance: self
-                                                .balances
-                                                .get_balance(order.user_id)
-                                                .await
-                                                .0,
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-                                self.redis
-                                    .push_message(
-                                        "db_queue",
-                                        &DbMessage::UpdateBalance {
-                                            user_id: bid.user_id,
-                                            balance: self.balances.get_balance(bid.user_id).await.0,
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-                                self.redis
-                                    .push_message("db_queue", &DbMessage::SaveTrade(trade.clone()))
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-
-                                self.redis
-                                    .publish_message(
-                                        "responses",
-                                        &MessageToApi::OrderMatched {
-                                            trade: trade.clone(),
-                                            client_id: client_id.to_string(),
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-
-                                remaining_quantity -= matched_quantity;
-                                if bid.quantity > matched_quantity {
-                                    let mut new_bid = bid.clone();
-                                    new_bid.quantity -= matched_quantity;
-                                    bids.push_front(new_bid);
-                                }
-                                if bids.is_empty() {
-                                    counter_book.bids.remove(&bid_price_cents);
-                                }
-                            }
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
+                // The pair is burned: both holdings shrink as clearing pays
+                // each seller their side
+                OrderType::Sell => {
+                    self.balances
+                        .credit_balance(
+                            resting.user_id,
+                            resting.price * matched_quantity as f64,
+                            EntryReason::TradeCredit,
+                            reference.clone(),
+                        )
+                        .await?;
+                    self.balances
+                        .credit_balance(
+                            order.user_id,
+                            price * matched_quantity as f64,
+                            EntryReason::TradeCredit,
+                            reference,
+                        )
+                        .await?;
                 }
             }
-            OrderType::Sell => {
-                while remaining_quantity > 0 {
-                    if let Some((&ask_price_cents, asks)) = counter_book.asks.iter_mut().next() {
-                        let ask_price = ask_price_cents as f64 / 100.0;
-                        if ask_price <= counter_price {
-                            if let Some(ask) = asks.pop_front() {
-                                let matched_quantity = remaining_quantity.min(ask.quantity);
-                                let trade = Trade {
-                                    buy_order_id: ask.id,
-                                    sell_order_id: order.id,
-                                    market_id: order.market_id.clone(),
-                                    option: order.option,
-                                    price: order.price,
-                                    quantity: matched_quantity,
-                                    timestamp: std::time::SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .unwrap()
-                                        .as_secs(),
-                                };
-                                trades.push(trade.clone());
-
-                                let amount = order.price * matched_quantity as f64;
-                                self.balances
-                                    .deduct_balance(ask.user_id, amount, self.commission_rate)
-                                    .await?;
-                                self.balances.credit_balance(order.user_id, amount).await?;
-                                self.redis
-                                    .push_message(
-                                        "db_queue",
-                                        &DbMessage::UpdateBalance {
-                                            user_id: ask.user_id,
-                                            balance: self.balances.get_balance(ask.user_id).await.0,
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-                                self.redis
-                                    .push_message(
-                                        "db_queue",
-                                        &DbMessage::UpdateBalance {
-                                            user_id: order.user_id,
-                                            balance: self
-                                                .balances
-                                                .get_balance(order.user_id)
-                                                .await
-                                                .0,
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-                                self.redis
-                                    .push_message("db_queue", &DbMessage::SaveTrade(trade.clone()))
-                                    .await
-                                    .map_err(|e| e.to_string())?;
-
-                                self.redis
-                                    .publish_message(
-                                        "responses",
-                                        &MessageToApi::OrderMatched {
-                                            trade: trade.clone(),
-                                            client_id: client_id.to_string(),
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| e.to_string())?;
+            self.record_position(order, matched_quantity, price).await;
+            self.record_position(&resting, matched_quantity, resting.price)
+                .await;
+            trades.push(trade.clone());
 
-                                remaining_quantity -= matched_quantity;
-                                if ask.quantity > matched_quantity {
-                                    let mut new_ask = ask.clone();
-                                    new_ask.quantity -= matched_quantity;
-                                    asks.push_front(new_ask);
-                                }
-                                if asks.is_empty() {
-                                    counter_book.asks.remove(&ask_price_cents);
-                                }
-                            }
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
-                }
+            self.save_balances(HashSet::from([order.user_id, resting.user_id]))
+                .await?;
+            self.persist(DbMessage::SaveTrade(trade.clone())).await?;
+            self.redis
+                .publish_message(
+                    "responses",
+                    &MessageToApi::OrderMatched {
+                        trade: trade.clone(),
+                        client_id: client_id.to_string(),
+                    },
+                )
+                .await
+                .map_err(|e| e.to_string())?;
+            self.publish_trade(&trade).await;
+
+            remaining_quantity -= matched_quantity;
+            if let Some(filled) = counter_book.fill_order(
+                resting.order_type.clone(),
+                resting.price,
+                resting.id,
+                matched_quantity,
+                resting.price,
+            ) {
+                self.save_order(&filled).await?;
             }
         }
         Ok(remaining_quantity)
@@ -942,8 +1274,8 @@ impl MatchingEngine {
             .ok_or("Market not found".to_string())?;
 
         let book = match option {
-            OptionType::Yes => yes_book,
-            OptionType::No => no_book,
+            OptionType::Yes => &mut *yes_book,
+            OptionType::No => &mut *no_book,
         };
 
         let price_cents = OrderBook::price_to_cents(price);
@@ -951,26 +1283,30 @@ impl MatchingEngine {
             OrderType::Buy => &book.bids,
             OrderType::Sell => &book.asks,
         };
-        if let Some(queue) = orders.get(&price_cents) {
-            if let Some(order) = queue.iter().find(|o| o.id == order_id) {
-                if order_type == OrderType::Buy {
-                    let amount = order.price * order.quantity as f64;
-                    self.balances.unlock_balance(order.user_id, amount).await?;
-                    self.redis
-                        .push_message(
-                            "db_queue",
-                            &DbMessage::UpdateBalance {
-                                user_id: order.user_id,
-                                balance: self.balances.get_balance(order.user_id).await.0,
-                            },
-                        )
-                        .await
-                        .map_err(|e| e.to_string())?;
-                }
-            }
+        if let Some(queue) = orders.get(&price_cents)
+            && let Some(order) = queue.iter().find(|o| o.id == order_id)
+            && order_type == OrderType::Buy
+        {
+            let amount = order.price * order.quantity as f64;
+            self.balances
+                .unlock_balance(order.user_id, amount, LedgerRef::Order(order.id))
+                .await?;
+            self.persist(DbMessage::UpdateBalance {
+                user_id: order.user_id,
+                balance: self.balances.get_balance(order.user_id).await.0,
+            })
+            .await?;
+        }
+
+        if let Some(mut order) = book.remove_order(order_type, price, order_id) {
+            order.cancel();
+            self.save_order(&order).await?;
         }
+        let book_update = BookUpdate::capture(&market_id, yes_book, no_book);
+        drop(markets);
 
-        book.remove_order(order_type, price, order_id);
+        self.publish_book_update(&market_id, &[], book_update)
+            .await?;
 
         self.redis
             .publish_message(
@@ -1025,15 +1361,7 @@ impl MatchingEngine {
         &self,
         market_id: String,
         client_id: String,
-    ) -> Result<
-        (
-            Vec<(f64, u32)>,
-            Vec<(f64, u32)>,
-            Vec<(f64, u32)>,
-            Vec<(f64, u32)>,
-        ),
-        String,
-    > {
+    ) -> Result<(PriceLevels, PriceLevels, PriceLevels, PriceLevels), String> {
         let markets = self.markets.read().await;
         let (yes_book, no_book) = markets
             .get(&market_id)
@@ -1061,4 +1389,2145 @@ impl MatchingEngine {
 
         Ok((yes_bids, yes_asks, no_bids, no_asks))
     }
+
+    pub async fn get_l3_snapshot(
+        &self,
+        market_id: String,
+        client_id: String,
+    ) -> Result<(L3BookSnapshot, L3BookSnapshot), String> {
+        let markets = self.markets.read().await;
+        let (yes_book, no_book) = markets
+            .get(&market_id)
+            .ok_or("Market not found".to_string())?;
+
+        let yes = yes_book.get_l3_snapshot();
+        let no = no_book.get_l3_snapshot();
+
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::L3Snapshot {
+                    market_id,
+                    yes: yes.clone(),
+                    no: no.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok((yes, no))
+    }
+
+    pub async fn get_candles(
+        &self,
+        market_id: String,
+        option: OptionType,
+        interval: CandleInterval,
+        limit: usize,
+        client_id: String,
+    ) -> Result<Vec<Candle>, String> {
+        if !self.markets.read().await.contains_key(&market_id) {
+            return Err("Market not found".to_string());
+        }
+
+        let candles = self
+            .candles
+            .get_candles(&market_id, option, interval, limit)
+            .await;
+
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::Candles {
+                    market_id,
+                    option,
+                    interval,
+                    candles: candles.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(candles)
+    }
+
+    pub async fn get_markets(&self, client_id: String) -> Result<Vec<Market>, String> {
+        let mut markets: Vec<Market> = self.listings.read().await.values().cloned().collect();
+        markets.sort_by(|a, b| (a.created_at, &a.market_id).cmp(&(b.created_at, &b.market_id)));
+
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::Markets {
+                    markets: markets.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(markets)
+    }
+
+    /// Tickers for one market, or for every market when `market_id` is `None`.
+    pub async fn get_tickers(
+        &self,
+        market_id: Option<String>,
+        client_id: String,
+    ) -> Result<Vec<Ticker>, String> {
+        let market_ids: Vec<String> = {
+            let markets = self.markets.read().await;
+            match market_id {
+                Some(market_id) if markets.contains_key(&market_id) => vec![market_id],
+                Some(_) => return Err("Market not found".to_string()),
+                None => markets.keys().cloned().collect(),
+            }
+        };
+
+        let mut tickers = Vec::with_capacity(market_ids.len());
+        for market_id in &market_ids {
+            tickers.push(self.ticker.get_ticker(market_id).await);
+        }
+
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::Tickers {
+                    tickers: tickers.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(tickers)
+    }
+
+    pub async fn get_combined_depth(
+        &self,
+        market_id: String,
+        client_id: String,
+    ) -> Result<CombinedBook, String> {
+        let markets = self.markets.read().await;
+        let (yes_book, no_book) = markets
+            .get(&market_id)
+            .ok_or("Market not found".to_string())?;
+
+        let book = OrderBook::get_combined_depth(&market_id, yes_book, no_book);
+
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::CombinedDepth {
+                    book: book.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(book)
+    }
+
+    pub async fn get_amm(
+        &self,
+        market_id: String,
+        client_id: String,
+    ) -> Result<AmmSnapshot, String> {
+        let amm = self
+            .amms
+            .read()
+            .await
+            .get(&market_id)
+            .map(|amm| amm.snapshot(&market_id))
+            .ok_or("Market has no AMM".to_string())?;
+
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::Amm {
+                    amm: amm.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(amm)
+    }
+
+    pub async fn place_stake(
+        &self,
+        user_id: u32,
+        market_id: String,
+        option: OptionType,
+        amount: f64,
+        client_id: String,
+    ) -> Result<PoolSnapshot, String> {
+        self.trading_status(&market_id).await?;
+        if let Err(code) = self.kill_switch.check(user_id).await {
+            return Err(format!("Stake rejected: {:?}", code));
+        }
+        let mut pools = self.pools.write().await;
+        let pool = pools
+            .get_mut(&market_id)
+            .ok_or("Pool market not found".to_string())?;
+
+        // Stakes stay locked in the user's balance until the pool resolves
+        self.balances.check_balance(user_id, amount, 0.0).await?;
+        let reference = LedgerRef::Market(market_id.clone());
+        self.balances
+            .lock_balance(user_id, amount, reference.clone())
+            .await?;
+        if let Err(e) = pool.stake(user_id, option, amount) {
+            self.balances
+                .unlock_balance(user_id, amount, reference)
+                .await?;
+            return Err(e);
+        }
+        let snapshot = pool.snapshot(&market_id, self.commission_rate);
+        drop(pools);
+
+        self.persist(DbMessage::UpdateBalance {
+            user_id,
+            balance: self.balances.get_balance(user_id).await.0,
+        })
+        .await?;
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::StakePlaced {
+                    pool: snapshot.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        self.redis
+            .publish_message(
+                "market_updates",
+                &WsMessage::Pool {
+                    pool: snapshot.clone(),
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(snapshot)
+    }
+
+    pub async fn get_pool(
+        &self,
+        market_id: String,
+        client_id: String,
+    ) -> Result<PoolSnapshot, String> {
+        let pool = self
+            .pools
+            .read()
+            .await
+            .get(&market_id)
+            .map(|pool| pool.snapshot(&market_id, self.commission_rate))
+            .ok_or("Pool market not found".to_string())?;
+
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::Pool {
+                    pool: pool.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(pool)
+    }
+
+    pub async fn resolve_market(
+        &self,
+        market_id: String,
+        resolution: Resolution,
+        client_id: String,
+    ) -> Result<(), String> {
+        if let Some((group_id, _)) = market_id.split_once(':')
+            && self.outcome_groups.read().await.contains_key(group_id)
+        {
+            return Err("Resolve the multi-outcome market, not a single outcome".to_string());
+        }
+        if self
+            .listings
+            .read()
+            .await
+            .get(&market_id)
+            .is_some_and(|market| market.status == MarketStatus::Resolved)
+        {
+            return Err("Market already resolved".to_string());
+        }
+        let is_pool = self.pools.read().await.contains_key(&market_id);
+        let is_book = self.markets.read().await.contains_key(&market_id);
+        let outcomes = self.outcome_groups.read().await.get(&market_id).cloned();
+        let range = self.scalar_ranges.read().await.get(&market_id).copied();
+
+        let mut pool_outcome = None;
+        let mut books = Vec::new();
+        match (&resolution, outcomes, range) {
+            (Resolution::Binary(outcome), None, None) if is_pool => pool_outcome = Some(*outcome),
+            (Resolution::Binary(outcome), None, None) if is_book => {
+                let yes_payout = match outcome {
+                    OptionType::Yes => SHARE_PAYOUT,
+                    OptionType::No => 0.0,
+                };
+                books.push((market_id.clone(), yes_payout));
+            }
+            (Resolution::Outcome(winner), Some(outcomes), None) => {
+                if !outcomes.contains(winner) {
+                    return Err(format!("Unknown outcome {}", winner));
+                }
+                // Yes pays on the winning outcome and No on every other one
+                for outcome in &outcomes {
+                    let yes_payout = if outcome == winner { SHARE_PAYOUT } else { 0.0 };
+                    books.push((outcome_market_id(&market_id, outcome), yes_payout));
+                }
+            }
+            (Resolution::Scalar(value), None, Some(range)) => {
+                if !value.is_finite() {
+                    return Err("Resolution value must be finite".to_string());
+                }
+                books.push((
+                    market_id.clone(),
+                    SHARE_PAYOUT * range.long_fraction(*value),
+                ));
+            }
+            (_, None, None) if !is_pool && !is_book => {
+                return Err("Market not found".to_string());
+            }
+            _ => return Err("Resolution does not match the market kind".to_string()),
+        }
+
+        // A resolution that failed part way is finished by resolving again
+        // the same way; anything else is refused until it completes
+        {
+            let mut resolving = self.resolving.write().await;
+            match resolving.get(&market_id) {
+                Some(pending) if *pending != resolution => {
+                    return Err(format!("Market is being resolved as {:?}", pending));
+                }
+                _ => {
+                    resolving.insert(market_id.clone(), resolution.clone());
+                }
+            }
+        }
+        self.close_market(&market_id).await?;
+        if let Some(outcome) = pool_outcome {
+            self.settle_pool(&market_id, outcome).await?;
+        }
+        for (book_id, yes_payout) in &books {
+            self.settle_book_market(book_id, *yes_payout).await?;
+        }
+        self.outcome_groups.write().await.remove(&market_id);
+        self.scalar_ranges.write().await.remove(&market_id);
+        self.resolving.write().await.remove(&market_id);
+        self.set_status(&market_id, MarketStatus::Resolved).await?;
+
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::MarketResolved {
+                    market_id: market_id.clone(),
+                    resolution: resolution.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        self.redis
+            .publish_message(
+                "market_updates",
+                &WsMessage::MarketResolved {
+                    market_id,
+                    resolution,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    // Each stake leaves the lock and is paid before it is dropped from the
+    // pool, so a rerun after a failure pays only what is still owed
+    async fn settle_pool(&self, market_id: &str, outcome: OptionType) -> Result<(), String> {
+        let settlement = {
+            let mut pools = self.pools.write().await;
+            match pools.get_mut(market_id) {
+                Some(pool) => pool.settle(outcome, self.commission_rate)?,
+                None => return Err("Pool market not found".to_string()),
+            }
+        };
+
+        for (user_id, option, staked, payout) in settlement.payouts {
+            let reference = LedgerRef::Market(market_id.to_string());
+            self.balances
+                .deduct_balance(user_id, staked, 0.0, reference.clone())
+                .await?;
+            self.balances
+                .credit_balance(user_id, payout, EntryReason::Payout, reference)
+                .await?;
+            if let Some(pool) = self.pools.write().await.get_mut(market_id) {
+                pool.paid(user_id, option);
+            }
+            self.persist(DbMessage::UpdateBalance {
+                user_id,
+                balance: self.balances.get_balance(user_id).await.0,
+            })
+            .await?;
+        }
+        // What the winners weren't paid stays in clearing until booked as
+        // the house's fee
+        self.balances
+            .ledger()
+            .post(
+                EntryReason::Fee,
+                LedgerRef::Market(market_id.to_string()),
+                LedgerAccount::Clearing,
+                LedgerAccount::Fees,
+                settlement.commission,
+            )
+            .await;
+        if let Some(pool) = self.pools.write().await.get_mut(market_id) {
+            pool.commission_booked();
+        }
+        tracing::info!(
+            "Resolved pool {} as {:?}, house commission {}",
+            market_id,
+            outcome,
+            settlement.commission
+        );
+        Ok(())
+    }
+
+    /// Closes one Yes/No book pair, paying `yes_payout` per Yes share held and
+    /// the rest of `SHARE_PAYOUT` per No share. Resting orders are cancelled
+    /// and the buys released first. Each holding is dropped as it is paid and
+    /// the books only once all are, so a rerun after a failure picks up where
+    /// it stopped and a book already settled is skipped.
+    async fn settle_book_market(&self, market_id: &str, yes_payout: f64) -> Result<(), String> {
+        let payout = |option: OptionType| match option {
+            OptionType::Yes => yes_payout,
+            OptionType::No => SHARE_PAYOUT - yes_payout,
+        };
+        if !self.markets.read().await.contains_key(market_id) {
+            return Ok(());
+        }
+        self.cancel_orders(market_id, None).await?;
+
+        let mut touched = HashSet::new();
+        for (user_id, option, quantity) in self.positions.holdings_in(market_id).await {
+            // Sells are covered by shares in hand, so every holding is long
+            // and paid out of what its buyers put into clearing
+            let amount = quantity as f64 * payout(option);
+            self.balances
+                .credit_balance(
+                    user_id,
+                    amount,
+                    EntryReason::Payout,
+                    LedgerRef::Market(market_id.to_string()),
+                )
+                .await?;
+            self.positions
+                .settle_holding(user_id, market_id, option)
+                .await;
+            if amount != 0.0 {
+                touched.insert(user_id);
+            }
+        }
+
+        self.markets.write().await.remove(market_id);
+        self.breakers.write().await.remove(market_id);
+        if let Some(amm) = self.amms.write().await.remove(market_id) {
+            // The house kept the opposite share of every one its AMM sold
+            self.balances
+                .ledger()
+                .post(
+                    EntryReason::Payout,
+                    LedgerRef::Market(market_id.to_string()),
+                    LedgerAccount::Clearing,
+                    LedgerAccount::House,
+                    amm.q_yes * payout(OptionType::No) + amm.q_no * payout(OptionType::Yes),
+                )
+                .await;
+            let owed = amm.q_yes * payout(OptionType::Yes) + amm.q_no * payout(OptionType::No);
+            tracing::info!(
+                "AMM for {} settled, house pnl {}",
+                market_id,
+                amm.house.cash - owed
+            );
+        }
+        self.save_balances(touched).await
+    }
+
+    async fn save_order(&self, order: &Order) -> Result<(), String> {
+        self.persist(DbMessage::SaveOrder(order.clone())).await
+    }
+
+    // Every write gets its own id here, so a push that reaches Redis twice
+    // is still applied once
+    async fn persist(&self, message: DbMessage) -> Result<(), String> {
+        self.redis
+            .push_message("db_queue", &DbWrite::new(message))
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn save_balances(&self, user_ids: HashSet<u32>) -> Result<(), String> {
+        for user_id in user_ids {
+            self.persist(DbMessage::UpdateBalance {
+                user_id,
+                balance: self.balances.get_balance(user_id).await.0,
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    // Trading and staking need the market, or for an outcome book its
+    // multi-outcome parent, to be open or in an auction and before its close
+    // time. The scheduled close only runs between messages, so the time is
+    // checked here too
+    async fn trading_status(&self, market_id: &str) -> Result<MarketStatus, String> {
+        let listings = self.listings.read().await;
+        let Some(market) = listing_for(&listings, market_id) else {
+            return Ok(MarketStatus::Open);
+        };
+        if market
+            .metadata
+            .close_time
+            .is_some_and(|close_time| now_ms() >= close_time * 1000)
+        {
+            return Err("Market is past its close time".to_string());
+        }
+        match market.status {
+            status @ (MarketStatus::Open | MarketStatus::Auction) => Ok(status),
+            status => Err(format!("Market is {:?}", status)),
+        }
+    }
+
+    async fn set_status(&self, market_id: &str, status: MarketStatus) -> Result<(), String> {
+        let market = {
+            let mut listings = self.listings.write().await;
+            let market = listings
+                .get_mut(market_id)
+                .ok_or("Market not found".to_string())?;
+            market.status = status;
+            market.clone()
+        };
+        // Timers set for the phase the market just left no longer apply
+        self.scheduler.next_phase(market_id).await;
+        self.persist(DbMessage::SaveMarket(market)).await?;
+        self.redis
+            .publish_message(
+                "market_updates",
+                &WsMessage::MarketStatus {
+                    market_id: market_id.to_string(),
+                    status,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Applies every scheduled close and auction uncross that is due.
+    pub async fn run_scheduled(&self) {
+        for (market_id, action) in self.scheduler.take_due(now_ms()).await {
+            let result = match action {
+                ScheduledAction::Close => self.close_market(&market_id).await,
+                ScheduledAction::Uncross => self.end_auction(&market_id).await,
+                ScheduledAction::Resume => self.resume_market(&market_id).await,
+            };
+            if let Err(e) = result {
+                tracing::error!("Failed to {:?} market {}: {}", action, market_id, e);
+            }
+        }
+    }
+
+    /// Stops trading: new orders are rejected from here on and every resting
+    /// order is cancelled. Books stay in place until the market resolves.
+    pub async fn close_market(&self, market_id: &str) -> Result<(), String> {
+        let book_ids = {
+            let listings = self.listings.read().await;
+            let market = listings
+                .get(market_id)
+                .ok_or("Market not found".to_string())?;
+            if !matches!(
+                market.status,
+                MarketStatus::Open | MarketStatus::Auction | MarketStatus::Halted
+            ) {
+                return Ok(());
+            }
+            market.book_ids()
+        };
+        self.set_status(market_id, MarketStatus::Closed).await?;
+
+        let mut cancelled = 0;
+        for book_id in &book_ids {
+            cancelled += self.cancel_orders(book_id, None).await?.len();
+        }
+        tracing::info!(
+            "Closed market {}, cancelled {} resting orders",
+            market_id,
+            cancelled
+        );
+        Ok(())
+    }
+
+    /// Pulls the resting orders of `user_id`, or every resting order, out of
+    /// a book pair and releases the funds locked by the buys.
+    async fn cancel_orders(
+        &self,
+        book_id: &str,
+        user_id: Option<u32>,
+    ) -> Result<Vec<Order>, String> {
+        let (cancelled, book_update) = {
+            let mut markets = self.markets.write().await;
+            let Some((yes_book, no_book)) = markets.get_mut(book_id) else {
+                return Ok(Vec::new());
+            };
+            let mut cancelled = Vec::new();
+            for book in [&mut *yes_book, &mut *no_book] {
+                let resting: Vec<Order> = book
+                    .bids
+                    .values()
+                    .chain(book.asks.values())
+                    .flatten()
+                    .filter(|o| user_id.is_none_or(|user_id| o.user_id == user_id))
+                    .cloned()
+                    .collect();
+                for order in resting {
+                    if let Some(mut order) =
+                        book.remove_order(order.order_type.clone(), order.price, order.id)
+                    {
+                        order.cancel();
+                        cancelled.push(order);
+                    }
+                }
+            }
+            (cancelled, BookUpdate::capture(book_id, yes_book, no_book))
+        };
+        if cancelled.is_empty() {
+            return Ok(cancelled);
+        }
+
+        let mut touched = HashSet::new();
+        for order in cancelled.iter().filter(|o| o.order_type == OrderType::Buy) {
+            let amount = order.price * order.quantity as f64;
+            self.balances
+                .unlock_balance(order.user_id, amount, LedgerRef::Order(order.id))
+                .await?;
+            touched.insert(order.user_id);
+        }
+        for order in &cancelled {
+            self.save_order(order).await?;
+        }
+        self.save_balances(touched).await?;
+        self.publish_book_update(book_id, &[], book_update).await?;
+        Ok(cancelled)
+    }
+
+    /// Stops matching for `duration_ms`: orders rest until the scheduled
+    /// uncross fills everything that crosses at one price.
+    pub async fn start_auction(&self, market_id: &str, duration_ms: u64) -> Result<(), String> {
+        self.set_status(market_id, MarketStatus::Auction).await?;
+        self.scheduler
+            .schedule(market_id, ScheduledAction::Uncross, now_ms() + duration_ms)
+            .await;
+        tracing::info!("Market {} in auction for {}ms", market_id, duration_ms);
+        Ok(())
+    }
+
+    pub async fn end_auction(&self, market_id: &str) -> Result<(), String> {
+        let book_ids = {
+            let listings = self.listings.read().await;
+            match listings.get(market_id) {
+                Some(market) if market.status == MarketStatus::Auction => market.book_ids(),
+                // Closed or already uncrossed in the meantime
+                _ => return Ok(()),
+            }
+        };
+        for book_id in &book_ids {
+            self.uncross_book(book_id).await?;
+        }
+        self.set_status(market_id, MarketStatus::Open).await?;
+        for book_id in &book_ids {
+            if self.take_breaker_trip(book_id).await {
+                self.trip_breaker(book_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Every fill of an uncross settles in one batch, so an auction either
+    // clears in full or leaves books and balances as they were. Buyers who
+    // cannot cover their commission lose their crossing orders and the
+    // clearing price is found again without them
+    async fn uncross_book(&self, book_id: &str) -> Result<(), String> {
+        let reference_cents = self
+            .ticker
+            .get_ticker(book_id)
+            .await
+            .yes
+            .last_price
+            .map(OrderBook::price_to_cents);
+
+        let mut markets = self.markets.write().await;
+        let Some((yes_book, no_book)) = markets.get_mut(book_id) else {
+            return Ok(());
+        };
+        let mut cancelled = Vec::new();
+        let uncross = loop {
+            let Some(uncross) = auction::uncross(yes_book, no_book, reference_cents) else {
+                break None;
+            };
+            let legs = auction_legs(&uncross);
+            let Err(short) = self.balances.settle(&legs, self.commission_rate).await else {
+                break Some(uncross);
+            };
+            for fill in &uncross.fills {
+                for order in [&fill.buyer, &fill.seller] {
+                    if !short.contains(&order.user_id) {
+                        continue;
+                    }
+                    let book = match order.option {
+                        OptionType::Yes => &mut *yes_book,
+                        OptionType::No => &mut *no_book,
+                    };
+                    if let Some(mut order) =
+                        book.remove_order(order.order_type.clone(), order.price, order.id)
+                    {
+                        order.cancel();
+                        cancelled.push(order);
+                    }
+                }
+            }
+        };
+
+        let mut filled = Vec::new();
+        let mut trades = Vec::new();
+        let (fills, yes_cents) = match &uncross {
+            Some(uncross) => (uncross.fills.as_slice(), uncross.price_cents),
+            None => (&[][..], 0),
+        };
+        for fill in fills {
+            for order in [&fill.buyer, &fill.seller] {
+                let book = match order.option {
+                    OptionType::Yes => &mut *yes_book,
+                    OptionType::No => &mut *no_book,
+                };
+                let fill_price = auction::execution_cents(order, yes_cents) as f64 / 100.0;
+                self.record_position(order, fill.quantity, fill_price).await;
+                filled.extend(book.fill_order(
+                    order.order_type.clone(),
+                    order.price,
+                    order.id,
+                    fill.quantity,
+                    fill_price,
+                ));
+            }
+            trades.push(auction_trade(fill, yes_cents));
+        }
+        let book_update = BookUpdate::capture(book_id, yes_book, no_book);
+        drop(markets);
+
+        let mut touched: HashSet<u32> = fills
+            .iter()
+            .flat_map(|fill| [fill.buyer.user_id, fill.seller.user_id])
+            .collect();
+        for order in cancelled.iter().filter(|o| o.order_type == OrderType::Buy) {
+            let amount = order.price * order.quantity as f64;
+            self.balances
+                .unlock_balance(order.user_id, amount, LedgerRef::Order(order.id))
+                .await?;
+            touched.insert(order.user_id);
+        }
+        if !cancelled.is_empty() {
+            tracing::warn!(
+                "Cancelled {} orders on {} whose owners could not pay for their auction fills",
+                cancelled.len(),
+                book_id
+            );
+        }
+        for order in cancelled.iter().chain(&filled) {
+            self.save_order(order).await?;
+        }
+        self.save_balances(touched).await?;
+        for trade in &trades {
+            self.persist(DbMessage::SaveTrade(trade.clone())).await?;
+            self.publish_trade(trade).await;
+        }
+
+        if let Some(uncross) = &uncross {
+            let price = uncross.price_cents as f64 / 100.0;
+            // The clearing price is what continuous trading is then held to
+            self.admit_fill(book_id, OptionType::Yes, price).await;
+            tracing::info!(
+                "Uncrossed {} at {} for {} shares",
+                book_id,
+                price,
+                uncross.volume
+            );
+            self.redis
+                .publish_message(
+                    "market_updates",
+                    &WsMessage::Price {
+                        market_id: book_id.to_string(),
+                        option: OptionType::Yes,
+                        price,
+                    },
+                )
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        self.publish_book_update(book_id, &trades, book_update)
+            .await
+    }
+
+    // Puts a fill at `price` on `option` to its book's circuit breaker before
+    // it executes; false means it would break the band and must not happen
+    async fn admit_fill(&self, book_id: &str, option: OptionType, price: f64) -> bool {
+        let mut breakers = self.breakers.write().await;
+        let Some(breaker) = breakers.get_mut(book_id) else {
+            return true;
+        };
+        let yes_price = match option {
+            OptionType::Yes => price,
+            OptionType::No => SHARE_PAYOUT - price,
+        };
+        breaker.admit(now_ms(), yes_price)
+    }
+
+    // The prices on `option` that fills on the book may trade at right now
+    async fn breaker_band(&self, book_id: &str, option: OptionType) -> Option<(f64, f64)> {
+        let (low, high) = self
+            .breakers
+            .write()
+            .await
+            .get_mut(book_id)?
+            .band(now_ms())?;
+        Some(match option {
+            OptionType::Yes => (low, high),
+            OptionType::No => (SHARE_PAYOUT - high, SHARE_PAYOUT - low),
+        })
+    }
+
+    async fn take_breaker_trip(&self, book_id: &str) -> bool {
+        self.breakers
+            .write()
+            .await
+            .get_mut(book_id)
+            .is_some_and(|breaker| breaker.take_trip())
+    }
+
+    /// Halts the listing `book_id` belongs to, or puts it into an auction,
+    /// for the breaker's cooldown. Resting orders stay in the book.
+    async fn trip_breaker(&self, book_id: &str) -> Result<(), String> {
+        let Some(config) = self.breakers.read().await.get(book_id).map(|b| b.config) else {
+            return Ok(());
+        };
+        let listing_id = {
+            let listings = self.listings.read().await;
+            match listing_for(&listings, book_id) {
+                Some(market) if market.status == MarketStatus::Open => market.market_id.clone(),
+                _ => return Ok(()),
+            }
+        };
+
+        tracing::warn!(
+            "Circuit breaker tripped on {}: {:?} for {}s",
+            book_id,
+            config.action,
+            config.cooldown_secs
+        );
+        let cooldown_ms = config.cooldown_secs * 1000;
+        match config.action {
+            BreakerAction::Halt => {
+                self.set_status(&listing_id, MarketStatus::Halted).await?;
+                self.scheduler
+                    .schedule(&listing_id, ScheduledAction::Resume, now_ms() + cooldown_ms)
+                    .await;
+            }
+            BreakerAction::Auction => self.start_auction(&listing_id, cooldown_ms).await?,
+        }
+        Ok(())
+    }
+
+    /// Reopens a halted market through a call auction, so the orders that
+    /// built up during the halt meet at one price rather than sweeping the
+    /// book. Pools have no book and open straight away.
+    pub async fn resume_market(&self, market_id: &str) -> Result<(), String> {
+        let kind = match self.listings.read().await.get(market_id) {
+            Some(market) if market.status == MarketStatus::Halted => market.kind,
+            _ => return Ok(()),
+        };
+        if kind == MarketKind::Parimutuel {
+            return self.set_status(market_id, MarketStatus::Open).await;
+        }
+        self.start_auction(market_id, REOPENING_AUCTION_MS).await
+    }
+
+    pub async fn set_risk_limits(
+        &self,
+        scope: RiskScope,
+        limits: RiskLimits,
+        client_id: String,
+    ) -> Result<(), String> {
+        if limits
+            .max_notional
+            .is_some_and(|n| !n.is_finite() || n <= 0.0)
+            || limits
+                .price_collar
+                .is_some_and(|c| !c.is_finite() || c <= 0.0)
+            || limits.max_position.is_some_and(|p| p < 0)
+        {
+            return Err("Risk limits must be positive".to_string());
+        }
+        self.risk.set_limits(scope.clone(), limits).await;
+        tracing::info!("Risk limits for {:?} set to {:?}", scope, limits);
+
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::RiskLimitsSet {
+                    scope,
+                    limits,
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // Runs the pre-trade limits against the order and the user's current
+    // exposure. A rejection is kept for the processor to answer with its
+    // reason code
+    #[allow(clippy::too_many_arguments)]
+    async fn check_risk(
+        &self,
+        user_id: u32,
+        market_id: &str,
+        option: OptionType,
+        order_type: OrderType,
+        price: f64,
+        quantity: u32,
+        client_id: &str,
+    ) -> Result<(), String> {
+        if let Err(code) = self.kill_switch.check(user_id).await {
+            let message = match code {
+                RejectCode::UserBlocked => format!("User {} is blocked", user_id),
+                _ => "Trading is halted".to_string(),
+            };
+            return self.reject_order(code, message, client_id).await;
+        }
+
+        let direction = yes_direction(option, &order_type);
+        let (open_orders, pending) = {
+            let markets = self.markets.read().await;
+            let mut open_orders = 0;
+            let mut pending = 0;
+            for (book_id, (yes_book, no_book)) in markets.iter() {
+                for order in yes_book
+                    .get_open_orders(user_id)
+                    .into_iter()
+                    .chain(no_book.get_open_orders(user_id))
+                {
+                    open_orders += 1;
+                    if book_id == market_id
+                        && yes_direction(order.option, &order.order_type) == direction
+                    {
+                        pending += direction * order.quantity as i64;
+                    }
+                }
+            }
+            (open_orders, pending)
+        };
+        let ticker = self.ticker.get_ticker(market_id).await;
+        let last_price = match option {
+            OptionType::Yes => ticker.yes.last_price,
+            OptionType::No => ticker.no.last_price,
+        };
+        let position = self
+            .positions
+            .get(user_id, market_id, OptionType::Yes)
+            .await
+            - self.positions.get(user_id, market_id, OptionType::No).await;
+        let exposure = OrderExposure {
+            option,
+            order_type,
+            price,
+            quantity,
+            open_orders,
+            position,
+            pending,
+            last_price,
+        };
+
+        let limits = self.risk.limits_for(user_id, market_id).await;
+        match RiskManager::check(&limits, &exposure) {
+            Ok(()) => Ok(()),
+            Err(rejection) => {
+                self.reject_order(rejection.code, rejection.message, client_id)
+                    .await
+            }
+        }
+    }
+
+    // Fails the order and keeps the reason code for the reply
+    async fn reject_order(
+        &self,
+        code: RejectCode,
+        message: String,
+        client_id: &str,
+    ) -> Result<(), String> {
+        let error = format!("Order rejected ({:?}): {}", code, message);
+        *self.rejection.write().await =
+            Some((client_id.to_string(), RiskRejection { code, message }));
+        Err(error)
+    }
+
+    /// Applies an operator command and records it in the audit trail.
+    pub async fn apply_admin_action(
+        &self,
+        action: AdminAction,
+        operator: String,
+        reason: String,
+        client_id: String,
+    ) -> Result<AuditEntry, String> {
+        if operator.trim().is_empty() || reason.trim().is_empty() {
+            return Err("Admin actions need an operator and a reason".to_string());
+        }
+        if let AdminAction::ResumeMarket { market_id } = &action {
+            if !self.listings.read().await.contains_key(market_id) {
+                return Err("Market not found".to_string());
+            }
+            self.resume_market(market_id).await?;
+        }
+        // Block first so nothing new rests while the books are swept
+        self.kill_switch.apply(&action).await;
+
+        let cancel_scope = match action {
+            AdminAction::BlockUser {
+                user_id,
+                cancel_orders: true,
+            } => Some(Some(user_id)),
+            AdminAction::HaltAll {
+                cancel_orders: true,
+            } => Some(None),
+            _ => None,
+        };
+        // A failed sweep doesn't undo the block or halt, so the action is
+        // audited either way and the failure recorded with it
+        let mut cancelled_orders = 0;
+        let mut error = None;
+        if let Some(user_id) = cancel_scope {
+            let book_ids: Vec<String> = self.markets.read().await.keys().cloned().collect();
+            for book_id in &book_ids {
+                match self.cancel_orders(book_id, user_id).await {
+                    Ok(cancelled) => cancelled_orders += cancelled.len(),
+                    Err(e) => {
+                        tracing::error!("Admin sweep of {} failed: {}", book_id, e);
+                        error.get_or_insert(format!("Cancelling orders on {}: {}", book_id, e));
+                    }
+                }
+            }
+        }
+
+        let entry = {
+            let mut audit = self.audit.write().await;
+            let mut next_id = self.next_audit_id.write().await;
+            let entry = AuditEntry {
+                id: *next_id,
+                timestamp: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+                operator,
+                reason,
+                action,
+                cancelled_orders,
+                error,
+            };
+            *next_id += 1;
+            audit.push(entry.clone());
+            entry
+        };
+        tracing::warn!("Admin action applied: {:?}", entry);
+
+        self.persist(DbMessage::SaveAudit(entry.clone())).await?;
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::AdminApplied {
+                    entry: entry.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(entry)
+    }
+
+    pub async fn get_audit_log(&self, client_id: String) -> Result<Vec<AuditEntry>, String> {
+        let entries = self.audit.read().await.clone();
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::AuditLog {
+                    entries: entries.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(entries)
+    }
+
+    /// Checks the collateral invariants across every account, book, pool and
+    /// AMM, alerts on anything broken and, with `halt_on_mismatch`, halts the
+    /// markets involved.
+    pub async fn reconcile(&self, halt_on_mismatch: bool) -> Result<Vec<Discrepancy>, String> {
+        let mut expected_locked: HashMap<u32, f64> = HashMap::new();
+        let mut resting_books: HashMap<u32, HashSet<String>> = HashMap::new();
+        for (book_id, (yes_book, no_book)) in self.markets.read().await.iter() {
+            for order in yes_book
+                .bids
+                .values()
+                .chain(no_book.bids.values())
+                .flatten()
+            {
+                *expected_locked.entry(order.user_id).or_default() +=
+                    order.price * order.quantity as f64;
+                resting_books
+                    .entry(order.user_id)
+                    .or_default()
+                    .insert(book_id.clone());
+            }
+        }
+        for pool in self.pools.read().await.values() {
+            for (user_id, amount) in pool.open_stakes() {
+                *expected_locked.entry(user_id).or_default() += amount;
+            }
+        }
+        let amm_inventory: HashMap<String, (i64, i64)> = self
+            .amms
+            .read()
+            .await
+            .iter()
+            .map(|(book_id, amm)| {
+                (
+                    book_id.clone(),
+                    (amm.q_yes.round() as i64, amm.q_no.round() as i64),
+                )
+            })
+            .collect();
+
+        let balances = self.balances.all_balances().await;
+        let accounts = self.balances.ledger().replay().await;
+        let mut discrepancies = reconciliation::check_locked(&balances, &expected_locked);
+        discrepancies.extend(reconciliation::check_cash(&balances, &accounts));
+        let net = self.positions.net_by_market().await;
+        discrepancies.extend(reconciliation::check_clearing(
+            accounts
+                .get(&LedgerAccount::Clearing)
+                .copied()
+                .unwrap_or(0.0),
+            &net,
+            &amm_inventory,
+        ));
+        discrepancies.extend(reconciliation::check_positions(&net, &amm_inventory));
+        if discrepancies.is_empty() {
+            return Ok(discrepancies);
+        }
+        for discrepancy in &discrepancies {
+            tracing::error!("Reconciliation mismatch: {:?}", discrepancy);
+        }
+
+        let mut halted = Vec::new();
+        if halt_on_mismatch {
+            // A user's lock leak is pinned on the books they have bids in;
+            // account-wide cash breaks have no market to halt
+            let book_ids: HashSet<String> = discrepancies
+                .iter()
+                .flat_map(|discrepancy| match discrepancy {
+                    Discrepancy::LockedFunds { user_id, .. } => resting_books
+                        .get(user_id)
+                        .into_iter()
+                        .flatten()
+                        .cloned()
+                        .collect(),
+                    Discrepancy::Positions { market_id, .. } => vec![market_id.clone()],
+                    _ => Vec::new(),
+                })
+                .collect();
+            let listing_ids: HashSet<String> = {
+                let listings = self.listings.read().await;
+                book_ids
+                    .iter()
+                    .filter_map(|book_id| listing_for(&listings, book_id))
+                    .filter(|market| {
+                        matches!(market.status, MarketStatus::Open | MarketStatus::Auction)
+                    })
+                    .map(|market| market.market_id.clone())
+                    .collect()
+            };
+            for listing_id in listing_ids {
+                self.set_status(&listing_id, MarketStatus::Halted).await?;
+                tracing::warn!("Halted {} after a reconciliation mismatch", listing_id);
+                halted.push(listing_id);
+            }
+        }
+
+        // Alerts name users and balances, so they go to the operators'
+        // channel rather than the public market feed
+        self.redis
+            .publish_message(
+                "alerts",
+                &WsMessage::ReconciliationAlert {
+                    discrepancies: discrepancies.clone(),
+                    halted,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(discrepancies)
+    }
+
+    /// Compares `user_id`'s balance by the ledger with the live one, along
+    /// with their latest journal entries.
+    pub async fn get_ledger(&self, user_id: u32, client_id: String) -> Result<LedgerAudit, String> {
+        let ledger = self.balances.ledger();
+        let rebuilt = ledger.rebuild_balance(user_id).await;
+        let balance = self.balances.get_balance(user_id).await;
+        let consistent =
+            (rebuilt.0 - balance.0).abs() < 1e-6 && (rebuilt.1 - balance.1).abs() < 1e-6;
+        if !consistent {
+            tracing::error!(
+                "Ledger for user {} rebuilds to {:?} but balance is {:?}",
+                user_id,
+                rebuilt,
+                balance
+            );
+        }
+        let audit = LedgerAudit {
+            user_id,
+            entries: ledger.entries_for(user_id).await,
+            rebuilt,
+            balance,
+            consistent,
+        };
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::Ledger {
+                    audit: audit.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(audit)
+    }
+
+    /// Hands journal entries recorded since the last flush to the db queue
+    /// and pushes the new balance of every user they touched to that user's
+    /// private stream. Every fill, cancel, transfer and payout is journalled,
+    /// so this catches them all.
+    pub async fn flush_ledger(&self) -> Result<(), String> {
+        let mut touched = BTreeSet::new();
+        // An entry counts as saved only once its push succeeds; the rest
+        // are retried on the next flush
+        let ledger = self.balances.ledger();
+        for entry in ledger.unsaved().await {
+            for posting in &entry.postings {
+                if let LedgerAccount::Available(user_id) | LedgerAccount::Locked(user_id) =
+                    posting.account
+                {
+                    touched.insert(user_id);
+                }
+            }
+            let id = entry.id;
+            self.persist(DbMessage::SaveJournal(entry)).await?;
+            ledger.mark_saved(id).await;
+        }
+        for user_id in touched {
+            let balance = self.balance_of(user_id).await;
+            self.redis
+                .publish_message("responses", &MessageToApi::BalanceUpdated { balance })
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    async fn balance_of(&self, user_id: u32) -> Balance {
+        let (available, locked) = self.balances.get_balance(user_id).await;
+        let positions = self.positions_of(user_id).await;
+        Balance {
+            user_id,
+            available,
+            locked,
+            positions,
+        }
+    }
+
+    // Every open holding of `user_id`, marked to the mid, else the last
+    // trade, else the AMM price
+    async fn positions_of(&self, user_id: u32) -> Vec<Position> {
+        let mut positions = Vec::new();
+        for (market_id, option, holding) in self.positions.for_user(user_id).await {
+            let mid = self.markets.read().await.get(&market_id).and_then(|books| {
+                let book = match option {
+                    OptionType::Yes => &books.0,
+                    OptionType::No => &books.1,
+                };
+                Some((book.best_bid()? + book.best_ask()?) / 2.0)
+            });
+            let ticker = self.ticker.get_ticker(&market_id).await;
+            let last_price = match option {
+                OptionType::Yes => ticker.yes.last_price,
+                OptionType::No => ticker.no.last_price,
+            };
+            let amm_price = self
+                .amms
+                .read()
+                .await
+                .get(&market_id)
+                .map(|amm| amm.price(option));
+            let mark_price = mid.or(last_price).or(amm_price);
+            let quantity = holding.quantity as f64;
+            positions.push(Position {
+                market_id,
+                option,
+                quantity: holding.quantity,
+                avg_cost: holding.avg_cost,
+                realized_pnl: holding.realized_pnl,
+                unrealized_pnl: mark_price.map_or(0.0, |mark| (mark - holding.avg_cost) * quantity),
+                mark_price,
+                value: mark_price.map_or(0.0, |mark| mark * quantity),
+            });
+        }
+        positions
+    }
+
+    pub async fn get_positions(
+        &self,
+        user_id: u32,
+        client_id: String,
+    ) -> Result<Vec<Position>, String> {
+        if !self.balances.has_account(user_id).await {
+            return Err(format!("Account {} not found", user_id));
+        }
+        let positions = self.positions_of(user_id).await;
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::Positions {
+                    positions: positions.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(positions)
+    }
+
+    pub async fn get_balance(&self, user_id: u32, client_id: String) -> Result<Balance, String> {
+        if !self.balances.has_account(user_id).await {
+            return Err(format!("Account {} not found", user_id));
+        }
+        let balance = self.balance_of(user_id).await;
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::Balance {
+                    balance: balance.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(balance)
+    }
+
+    pub async fn create_account(&self, user_id: u32, client_id: String) -> Result<(), String> {
+        self.balances.create_account(user_id).await?;
+        self.save_balances(HashSet::from([user_id])).await?;
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::AccountCreated { user_id, client_id },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Picks the accounts back up after a restart. Books, positions, AMMs and
+    /// pools don't survive one, so it refuses while the stored ledger still
+    /// holds collateral for open shares or funds locked beyond what the
+    /// resting orders reserved; those markets have to be resolved before the
+    /// engine stops. Otherwise the resting orders are cancelled and what they
+    /// locked goes back to available.
+    pub async fn restore_accounts(&self, stored: StoredAccounts) -> Result<(), String> {
+        let clearing = stored
+            .ledger
+            .get(&LedgerAccount::Clearing)
+            .copied()
+            .unwrap_or(0.0);
+        let locked: f64 = stored
+            .ledger
+            .iter()
+            .filter(|(account, _)| matches!(account, LedgerAccount::Locked(_)))
+            .map(|(_, amount)| amount)
+            .sum();
+        let reserved: f64 = stored
+            .open_orders
+            .iter()
+            .filter(|order| order.order_type == OrderType::Buy)
+            .map(|order| order.price * order.quantity as f64)
+            .sum();
+        if clearing.abs() > 1e-6 || locked - reserved > 1e-6 {
+            return Err(format!(
+                "Unsettled markets: {} held against open shares and {} locked beyond resting \
+                 orders; resolve every market before stopping the engine",
+                clearing,
+                locked - reserved
+            ));
+        }
+
+        let accounts = stored.user_ids.len();
+        self.balances.restore(&stored.user_ids, stored.ledger).await;
+
+        let mut touched = HashSet::new();
+        for mut order in stored.open_orders {
+            if order.order_type == OrderType::Buy {
+                let amount = order.price * order.quantity as f64;
+                self.balances
+                    .unlock_balance(order.user_id, amount, LedgerRef::Order(order.id))
+                    .await?;
+            }
+            order.cancel();
+            self.save_order(&order).await?;
+            touched.insert(order.user_id);
+        }
+        tracing::info!(
+            "Restored {} accounts, cancelled the orders of {}",
+            accounts,
+            touched.len()
+        );
+        self.save_balances(touched).await
+    }
+
+    /// Opens an empty account for `user_id` unless it already has one.
+    pub async fn open_account(&self, user_id: u32) {
+        if self.balances.create_account(user_id).await.is_ok() {
+            tracing::info!("Opened account {}", user_id);
+        }
+    }
+
+    pub async fn transfer(
+        &self,
+        user_id: u32,
+        kind: TransferKind,
+        amount: f64,
+        client_id: String,
+    ) -> Result<Transfer, String> {
+        // A blocked user, or a halted platform, can't take money out
+        if kind == TransferKind::Withdrawal
+            && let Err(code) = self.kill_switch.check(user_id).await
+        {
+            return Err(format!("Withdrawal rejected: {:?}", code));
+        }
+        let id = {
+            let mut next_id = self.next_transfer_id.write().await;
+            *next_id += 1;
+            *next_id - 1
+        };
+        let reference = LedgerRef::Transfer(id);
+        let (available, locked) = match kind {
+            TransferKind::Deposit => self.balances.deposit(user_id, amount, reference).await?,
+            TransferKind::Withdrawal => self.balances.withdraw(user_id, amount, reference).await?,
+        };
+        let transfer = Transfer {
+            id,
+            user_id,
+            kind,
+            amount,
+            available,
+            locked,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        };
+
+        self.persist(DbMessage::SaveTransfer(transfer.clone()))
+            .await?;
+        self.save_balances(HashSet::from([user_id])).await?;
+        self.redis
+            .publish_message(
+                "responses",
+                &MessageToApi::TransferCompleted {
+                    transfer: transfer.clone(),
+                    client_id,
+                },
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(transfer)
+    }
+}
+
+// The listing a book belongs to: its own, or its multi-outcome parent's
+fn listing_for<'a>(listings: &'a HashMap<String, Market>, book_id: &str) -> Option<&'a Market> {
+    listings.get(book_id).or_else(|| {
+        book_id
+            .split_once(':')
+            .and_then(|(group_id, _)| listings.get(group_id))
+    })
+}
+
+// Both sides of an auction fill pay or receive the clearing price. Buys
+// locked their limit price, so the improvement goes back to them
+fn auction_legs(uncross: &auction::Uncross) -> Vec<Leg> {
+    let mut legs = Vec::with_capacity(uncross.fills.len() * 2);
+    for fill in &uncross.fills {
+        let reference = LedgerRef::Trade {
+            buy_order_id: fill.buyer.id,
+            sell_order_id: fill.seller.id,
+        };
+        for order in [&fill.buyer, &fill.seller] {
+            let price = auction::execution_cents(order, uncross.price_cents) as f64 / 100.0;
+            let amount = price * fill.quantity as f64;
+            legs.push(match order.order_type {
+                OrderType::Buy => Leg::Buy {
+                    user_id: order.user_id,
+                    amount,
+                    locked: (order.price * fill.quantity as f64).max(amount),
+                    reference: reference.clone(),
+                },
+                OrderType::Sell => Leg::Sell {
+                    user_id: order.user_id,
+                    amount,
+                    reference: reference.clone(),
+                },
+            });
+        }
+    }
+    legs
+}
+
+// Two No orders make a plain No trade; anything involving Yes is reported as
+// Yes at the clearing price
+fn auction_trade(fill: &AuctionFill, yes_cents: u64) -> Trade {
+    let (buy_order_id, sell_order_id, option) =
+        if fill.buyer.option == OptionType::No && fill.seller.option == OptionType::No {
+            (fill.seller.id, fill.buyer.id, OptionType::No)
+        } else {
+            (fill.buyer.id, fill.seller.id, OptionType::Yes)
+        };
+    let price_cents = match option {
+        OptionType::Yes => yes_cents,
+        OptionType::No => COMPLEMENT_CENTS - yes_cents,
+    };
+    Trade {
+        buy_order_id,
+        sell_order_id,
+        market_id: fill.buyer.market_id.clone(),
+        option,
+        price: price_cents as f64 / 100.0,
+        quantity: fill.quantity,
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+    }
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::market::CircuitBreakerConfig;
+
+    const MARKET: &str = "m";
+
+    async fn engine(trading: TradingConfig) -> MatchingEngine {
+        let engine = MatchingEngine::new(RedisManager::offline());
+        engine
+            .create_market(
+                MARKET.to_string(),
+                "Will it?".to_string(),
+                MarketKind::OrderBook,
+                Vec::new(),
+                None,
+                MarketMetadata::default(),
+                trading,
+                "test".to_string(),
+            )
+            .await
+            .unwrap();
+        for user_id in 1..=3 {
+            engine
+                .create_account(user_id, "test".to_string())
+                .await
+                .unwrap();
+            engine
+                .transfer(user_id, TransferKind::Deposit, 1000.0, "test".to_string())
+                .await
+                .unwrap();
+        }
+        engine
+    }
+
+    async fn place(
+        engine: &MatchingEngine,
+        user_id: u32,
+        option: OptionType,
+        order_type: OrderType,
+        price: f64,
+        quantity: u32,
+    ) -> (Order, Vec<Trade>) {
+        engine
+            .place_order(
+                user_id,
+                MARKET.to_string(),
+                option,
+                order_type,
+                price,
+                quantity,
+                "test".to_string(),
+            )
+            .await
+            .unwrap()
+    }
+
+    // Gives `yes_user` and `no_user` `quantity` shares each by minting pairs
+    // at 5.0, so they have something to sell
+    async fn mint(engine: &MatchingEngine, yes_user: u32, no_user: u32, quantity: u32) {
+        place(
+            engine,
+            no_user,
+            OptionType::No,
+            OrderType::Buy,
+            5.0,
+            quantity,
+        )
+        .await;
+        let (order, _) = place(
+            engine,
+            yes_user,
+            OptionType::Yes,
+            OrderType::Buy,
+            5.0,
+            quantity,
+        )
+        .await;
+        assert_eq!(order.quantity, 0);
+    }
+
+    async fn assert_reconciles(engine: &MatchingEngine) {
+        let discrepancies = engine.reconcile(false).await.unwrap();
+        assert!(discrepancies.is_empty(), "{:?}", discrepancies);
+    }
+
+    #[tokio::test]
+    async fn book_fills_reconcile() {
+        let engine = engine(TradingConfig::default()).await;
+        mint(&engine, 1, 3, 5).await;
+        mint(&engine, 3, 2, 2).await;
+        place(&engine, 1, OptionType::Yes, OrderType::Sell, 6.0, 5).await;
+        let (_, trades) = place(&engine, 2, OptionType::Yes, OrderType::Buy, 6.5, 8).await;
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, 6.0);
+        assert_reconciles(&engine).await;
+
+        let (_, trades) = place(&engine, 3, OptionType::Yes, OrderType::Sell, 6.0, 2).await;
+        assert_eq!(trades[0].price, 6.5);
+        assert_reconciles(&engine).await;
+    }
+
+    #[tokio::test]
+    async fn counter_book_mint_reconciles() {
+        let engine = engine(TradingConfig::default()).await;
+        place(&engine, 1, OptionType::No, OrderType::Buy, 4.0, 5).await;
+        let (order, trades) = place(&engine, 2, OptionType::Yes, OrderType::Buy, 6.5, 5).await;
+        assert_eq!(order.quantity, 0);
+        assert_eq!(trades[0].price, 6.0);
+        assert_eq!(engine.positions.get(1, MARKET, OptionType::No).await, 5);
+        assert_eq!(engine.positions.get(2, MARKET, OptionType::Yes).await, 5);
+        // Both buyers paid their side and nothing stays locked
+        assert_eq!(engine.balances.get_balance(1).await.1, 0.0);
+        assert_eq!(engine.balances.get_balance(2).await.1, 0.0);
+        assert_reconciles(&engine).await;
+    }
+
+    #[tokio::test]
+    async fn counter_book_burn_reconciles() {
+        let engine = engine(TradingConfig::default()).await;
+        place(&engine, 1, OptionType::No, OrderType::Buy, 4.0, 5).await;
+        place(&engine, 2, OptionType::Yes, OrderType::Buy, 6.0, 5).await;
+
+        place(&engine, 1, OptionType::No, OrderType::Sell, 3.0, 5).await;
+        let (_, trades) = place(&engine, 2, OptionType::Yes, OrderType::Sell, 6.0, 5).await;
+        assert_eq!(trades[0].price, 7.0);
+        assert_eq!(engine.positions.get(1, MARKET, OptionType::No).await, 0);
+        assert_eq!(engine.positions.get(2, MARKET, OptionType::Yes).await, 0);
+        assert_reconciles(&engine).await;
+    }
+
+    #[tokio::test]
+    async fn burn_only_takes_shares_still_held() {
+        let engine = engine(TradingConfig::default()).await;
+        mint(&engine, 1, 2, 5).await;
+        place(&engine, 2, OptionType::No, OrderType::Sell, 3.0, 5).await;
+        // The No seller's shares are gone by the time the Yes seller arrives
+        engine
+            .positions
+            .settle_holding(2, MARKET, OptionType::No)
+            .await;
+
+        let (order, trades) = place(&engine, 1, OptionType::Yes, OrderType::Sell, 6.0, 5).await;
+        assert!(trades.is_empty());
+        assert_eq!(order.quantity, 5);
+        assert_eq!(engine.positions.get(1, MARKET, OptionType::Yes).await, 5);
+        assert!(engine.markets.read().await[MARKET].1.asks.is_empty());
+        assert_eq!(
+            engine.balances.get_balance(2).await.0,
+            1000.0 - 25.0 * (1.0 + engine.commission_rate)
+        );
+    }
+
+    #[tokio::test]
+    async fn no_ask_does_not_cross_yes_bid() {
+        let engine = engine(TradingConfig::default()).await;
+        mint(&engine, 3, 1, 5).await;
+        place(&engine, 1, OptionType::No, OrderType::Sell, 3.0, 5).await;
+        let (_, trades) = place(&engine, 2, OptionType::Yes, OrderType::Buy, 6.0, 5).await;
+        assert!(trades.is_empty());
+        assert_reconciles(&engine).await;
+    }
+
+    #[tokio::test]
+    async fn amm_fills_reconcile() {
+        let engine = engine(TradingConfig {
+            amm_liquidity: Some(100.0),
+            ..TradingConfig::default()
+        })
+        .await;
+        let (order, trades) = place(&engine, 1, OptionType::Yes, OrderType::Buy, 6.0, 10).await;
+        assert_eq!(order.quantity, 0);
+        assert_eq!(trades.len(), 1);
+        assert_reconciles(&engine).await;
+
+        place(&engine, 1, OptionType::Yes, OrderType::Sell, 4.0, 10).await;
+        assert_eq!(engine.positions.get(1, MARKET, OptionType::Yes).await, 0);
+        assert_reconciles(&engine).await;
+    }
+
+    #[tokio::test]
+    async fn amm_buys_back_only_held_shares() {
+        let engine = engine(TradingConfig {
+            amm_liquidity: Some(100.0),
+            ..TradingConfig::default()
+        })
+        .await;
+        place(&engine, 1, OptionType::Yes, OrderType::Buy, 6.0, 4).await;
+        let sell = |user_id, quantity| {
+            engine.place_order(
+                user_id,
+                MARKET.to_string(),
+                OptionType::Yes,
+                OrderType::Sell,
+                4.0,
+                quantity,
+                "test".to_string(),
+            )
+        };
+        assert!(sell(1, 10).await.is_err());
+        assert!(sell(2, 5).await.is_err());
+
+        let (order, trades) = sell(1, 4).await.unwrap();
+        assert_eq!(trades[0].quantity, 4);
+        assert_eq!(order.quantity, 0);
+        assert_reconciles(&engine).await;
+    }
+
+    #[tokio::test]
+    async fn amm_house_is_paid_its_side_at_resolution() {
+        let engine = engine(TradingConfig {
+            amm_liquidity: Some(100.0),
+            ..TradingConfig::default()
+        })
+        .await;
+        place(&engine, 1, OptionType::Yes, OrderType::Buy, 6.0, 10).await;
+        place(&engine, 2, OptionType::No, OrderType::Buy, 6.0, 4).await;
+        let cash = engine.amms.read().await[MARKET].house.cash;
+        let accounts = engine.balances.ledger().replay().await;
+        // Every share sold is backed by a full payout in clearing
+        assert!((accounts[&LedgerAccount::Clearing] - 14.0 * SHARE_PAYOUT).abs() < 1e-9);
+        assert_reconciles(&engine).await;
+
+        resolve(&engine, OptionType::Yes).await.unwrap();
+        let accounts = engine.balances.ledger().replay().await;
+        assert!(accounts[&LedgerAccount::Clearing].abs() < 1e-9);
+        assert!((accounts[&LedgerAccount::House] - (cash - 10.0 * SHARE_PAYOUT)).abs() < 1e-9);
+        assert_reconciles(&engine).await;
+    }
+
+    #[tokio::test]
+    async fn amm_fill_outside_the_band_is_refused() {
+        let engine = engine(TradingConfig {
+            amm_liquidity: Some(100.0),
+            circuit_breaker: Some(CircuitBreakerConfig {
+                enabled: true,
+                max_move: 0.5,
+                action: BreakerAction::Halt,
+                ..CircuitBreakerConfig::default()
+            }),
+            ..TradingConfig::default()
+        })
+        .await;
+        // Trades at 6.0 on the book while the AMM still quotes 5.0
+        place(&engine, 3, OptionType::No, OrderType::Buy, 4.0, 1).await;
+        place(&engine, 1, OptionType::Yes, OrderType::Buy, 6.0, 1).await;
+
+        let (order, trades) = place(&engine, 2, OptionType::Yes, OrderType::Buy, 6.5, 3).await;
+        assert!(trades.is_empty());
+        assert_eq!(order.quantity, 3);
+        assert_eq!(engine.amms.read().await[MARKET].q_yes, 0.0);
+        assert_eq!(
+            engine.listings.read().await[MARKET].status,
+            MarketStatus::Halted
+        );
+        assert_reconciles(&engine).await;
+    }
+
+    #[tokio::test]
+    async fn auction_uncross_reconciles() {
+        let engine = engine(TradingConfig::default()).await;
+        mint(&engine, 2, 3, 3).await;
+        engine.start_auction(MARKET, 60_000).await.unwrap();
+        place(&engine, 1, OptionType::Yes, OrderType::Buy, 6.0, 5).await;
+        place(&engine, 2, OptionType::Yes, OrderType::Sell, 5.0, 3).await;
+        place(&engine, 3, OptionType::No, OrderType::Buy, 5.0, 2).await;
+        engine.end_auction(MARKET).await.unwrap();
+
+        assert_eq!(engine.positions.get(1, MARKET, OptionType::Yes).await, 5);
+        assert_reconciles(&engine).await;
+    }
+
+    #[tokio::test]
+    async fn auction_cancels_buyers_short_of_commission() {
+        let engine = engine(TradingConfig::default()).await;
+        mint(&engine, 3, 2, 5).await;
+        engine.start_auction(MARKET, 60_000).await.unwrap();
+        place(&engine, 1, OptionType::Yes, OrderType::Buy, 6.0, 5).await;
+        place(&engine, 2, OptionType::Yes, OrderType::Buy, 5.5, 5).await;
+        place(&engine, 3, OptionType::Yes, OrderType::Sell, 5.0, 5).await;
+        // Nothing left over for the commission on user 1's fill
+        engine
+            .transfer(1, TransferKind::Withdrawal, 970.0, "test".to_string())
+            .await
+            .unwrap();
+        engine.end_auction(MARKET).await.unwrap();
+
+        assert_eq!(engine.positions.get(1, MARKET, OptionType::Yes).await, 0);
+        assert_eq!(engine.balances.get_balance(1).await, (30.0, 0.0));
+        assert_eq!(engine.positions.get(2, MARKET, OptionType::Yes).await, 5);
+        assert_reconciles(&engine).await;
+    }
+
+    fn breaker(enabled: bool) -> TradingConfig {
+        TradingConfig {
+            circuit_breaker: Some(CircuitBreakerConfig {
+                enabled,
+                max_move: 1.0,
+                action: BreakerAction::Halt,
+                ..CircuitBreakerConfig::default()
+            }),
+            ..TradingConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn breaker_stops_matching_at_first_fill_outside_band() {
+        let engine = engine(breaker(true)).await;
+        mint(&engine, 1, 3, 6).await;
+        place(&engine, 1, OptionType::Yes, OrderType::Sell, 5.0, 1).await;
+        place(&engine, 2, OptionType::Yes, OrderType::Buy, 5.0, 1).await;
+
+        place(&engine, 1, OptionType::Yes, OrderType::Sell, 5.5, 2).await;
+        place(&engine, 1, OptionType::Yes, OrderType::Sell, 7.0, 3).await;
+        let (order, trades) = place(&engine, 2, OptionType::Yes, OrderType::Buy, 7.5, 5).await;
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, 5.5);
+        assert_eq!(order.quantity, 3);
+        assert_eq!(
+            engine.listings.read().await[MARKET].status,
+            MarketStatus::Halted
+        );
+        assert_reconciles(&engine).await;
+
+        engine.resume_market(MARKET).await.unwrap();
+        assert_eq!(
+            engine.listings.read().await[MARKET].status,
+            MarketStatus::Auction
+        );
+    }
+
+    #[tokio::test]
+    async fn disabled_breaker_lets_every_fill_through() {
+        let engine = engine(breaker(false)).await;
+        mint(&engine, 1, 3, 4).await;
+        place(&engine, 1, OptionType::Yes, OrderType::Sell, 5.0, 1).await;
+        place(&engine, 2, OptionType::Yes, OrderType::Buy, 5.0, 1).await;
+
+        place(&engine, 1, OptionType::Yes, OrderType::Sell, 7.0, 3).await;
+        let (order, _) = place(&engine, 2, OptionType::Yes, OrderType::Buy, 7.5, 3).await;
+        assert_eq!(order.quantity, 0);
+        assert_eq!(
+            engine.listings.read().await[MARKET].status,
+            MarketStatus::Open
+        );
+    }
+
+    async fn resolve(engine: &MatchingEngine, outcome: OptionType) -> Result<(), String> {
+        engine
+            .resolve_market(
+                MARKET.to_string(),
+                Resolution::Binary(outcome),
+                "test".to_string(),
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn resolution_pays_holdings_once() {
+        let engine = engine(TradingConfig::default()).await;
+        place(&engine, 1, OptionType::No, OrderType::Buy, 4.0, 5).await;
+        place(&engine, 2, OptionType::Yes, OrderType::Buy, 6.0, 5).await;
+        place(&engine, 3, OptionType::Yes, OrderType::Buy, 5.0, 2).await;
+        resolve(&engine, OptionType::Yes).await.unwrap();
+
+        assert!(engine.positions.holdings_in(MARKET).await.is_empty());
+        let (available, locked) = engine.balances.get_balance(2).await;
+        assert!((available - (1000.0 + 20.0 - 30.0 * engine.commission_rate)).abs() < 1e-9);
+        assert_eq!(locked, 0.0);
+        assert_eq!(engine.balances.get_balance(3).await, (1000.0, 0.0));
+        assert_reconciles(&engine).await;
+
+        assert!(resolve(&engine, OptionType::No).await.is_err());
+        assert!(resolve(&engine, OptionType::Yes).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn pool_commission_is_booked_as_a_fee() {
+        let engine = engine(TradingConfig::default()).await;
+        engine
+            .create_market(
+                "p".to_string(),
+                "Will it?".to_string(),
+                MarketKind::Parimutuel,
+                Vec::new(),
+                None,
+                MarketMetadata::default(),
+                TradingConfig::default(),
+                "test".to_string(),
+            )
+            .await
+            .unwrap();
+        // A pool has no book, so an order on it must not lock anything
+        let order = engine
+            .place_order(
+                1,
+                "p".to_string(),
+                OptionType::Yes,
+                OrderType::Buy,
+                5.0,
+                10,
+                "test".to_string(),
+            )
+            .await;
+        assert!(order.is_err());
+        assert_eq!(engine.balances.get_balance(1).await, (1000.0, 0.0));
+
+        for (user_id, option) in [(1, OptionType::Yes), (2, OptionType::No)] {
+            engine
+                .place_stake(user_id, "p".to_string(), option, 50.0, "test".to_string())
+                .await
+                .unwrap();
+        }
+        engine
+            .resolve_market(
+                "p".to_string(),
+                Resolution::Binary(OptionType::Yes),
+                "test".to_string(),
+            )
+            .await
+            .unwrap();
+
+        let fees = engine.balances.ledger().replay().await[&LedgerAccount::Fees];
+        assert!((fees - 100.0 * engine.commission_rate).abs() < 1e-9);
+        assert_reconciles(&engine).await;
+    }
+
+    #[tokio::test]
+    async fn uncovered_sell_is_rejected() {
+        let engine = engine(TradingConfig::default()).await;
+        mint(&engine, 1, 2, 5).await;
+        let sell = |quantity| {
+            engine.place_order(
+                1,
+                MARKET.to_string(),
+                OptionType::Yes,
+                OrderType::Sell,
+                6.0,
+                quantity,
+                "test".to_string(),
+            )
+        };
+        assert!(sell(6).await.is_err());
+        // Shares offered by a resting sell can't back a second one
+        sell(3).await.unwrap();
+        assert!(sell(3).await.is_err());
+        sell(2).await.unwrap();
+
+        place(&engine, 3, OptionType::Yes, OrderType::Buy, 6.0, 5).await;
+        assert_eq!(engine.positions.get(1, MARKET, OptionType::Yes).await, 0);
+        resolve(&engine, OptionType::Yes).await.unwrap();
+
+        // The seller kept their proceeds and owes nothing on resolution
+        assert_eq!(
+            engine.balances.get_balance(1).await,
+            (1000.0 - 25.0 * (1.0 + engine.commission_rate) + 30.0, 0.0)
+        );
+        assert_reconciles(&engine).await;
+    }
+
+    #[tokio::test]
+    async fn admin_action_is_audited_when_the_sweep_fails() {
+        let engine = engine(TradingConfig::default()).await;
+        place(&engine, 1, OptionType::Yes, OrderType::Buy, 5.0, 5).await;
+        // Leave less locked than the order holds so releasing it fails
+        engine
+            .balances
+            .unlock_balance(1, 10.0, LedgerRef::Order(0))
+            .await
+            .unwrap();
+        let entry = engine
+            .apply_admin_action(
+                AdminAction::BlockUser {
+                    user_id: 1,
+                    cancel_orders: true,
+                },
+                "ops".to_string(),
+                "test".to_string(),
+                "test".to_string(),
+            )
+            .await
+            .unwrap();
+
+        assert!(entry.error.is_some());
+        assert_eq!(engine.audit.read().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn restored_accounts_release_what_resting_orders_locked() {
+        let engine = MatchingEngine::new(RedisManager::offline());
+        let order = Order::new(
+            3,
+            1,
+            MARKET.to_string(),
+            OptionType::Yes,
+            OrderType::Buy,
+            5.0,
+            2,
+        );
+        engine
+            .restore_accounts(StoredAccounts {
+                user_ids: vec![1, 2],
+                ledger: HashMap::from([
+                    (LedgerAccount::External, -100.0),
+                    (LedgerAccount::Available(1), 90.0),
+                    (LedgerAccount::Locked(1), 10.0),
+                ]),
+                open_orders: vec![order],
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(engine.balances.get_balance(1).await, (100.0, 0.0));
+        assert!(engine.balances.has_account(2).await);
+        assert_reconciles(&engine).await;
+    }
+
+    #[tokio::test]
+    async fn restore_refuses_while_markets_are_unsettled() {
+        let restore = |ledger: Vec<(LedgerAccount, f64)>| async move {
+            MatchingEngine::new(RedisManager::offline())
+                .restore_accounts(StoredAccounts {
+                    user_ids: vec![1],
+                    ledger: ledger.into_iter().collect(),
+                    open_orders: Vec::new(),
+                })
+                .await
+        };
+        // Collateral for shares whose holdings are gone
+        assert!(
+            restore(vec![
+                (LedgerAccount::External, -100.0),
+                (LedgerAccount::Available(1), 50.0),
+                (LedgerAccount::Clearing, 50.0),
+            ])
+            .await
+            .is_err()
+        );
+        // A pool stake with no pool to settle it
+        assert!(
+            restore(vec![
+                (LedgerAccount::External, -100.0),
+                (LedgerAccount::Available(1), 80.0),
+                (LedgerAccount::Locked(1), 20.0),
+            ])
+            .await
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel_reconciles() {
+        let engine = engine(TradingConfig::default()).await;
+        let (order, _) = place(&engine, 1, OptionType::Yes, OrderType::Buy, 5.0, 5).await;
+        engine
+            .cancel_order(
+                MARKET.to_string(),
+                OptionType::Yes,
+                OrderType::Buy,
+                5.0,
+                order.id,
+                "test".to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(engine.balances.get_balance(1).await, (1000.0, 0.0));
+        assert_reconciles(&engine).await;
+    }
 }
@@ -1,4 +1,17 @@
+pub mod amm;
+pub mod auction;
 pub mod balance_manager;
+pub mod candles;
+pub mod circuit_breaker;
+pub mod kill_switch;
+pub mod ledger;
 pub mod matching_engine;
 pub mod order_book;
+pub mod parimutuel;
+pub mod positions;
 pub mod processor;
+pub mod reconciliation;
+pub mod risk;
+pub mod scheduler;
+pub mod strategy;
+pub mod ticker;
@@ -1,11 +1,29 @@
-use crate::types::order::{OptionType, Order, OrderType};
-use std::collections::{BTreeMap, VecDeque};
+use crate::types::{
+    market_data::{CombinedBook, CombinedLevel, L3BookSnapshot, L3Event, L3EventKind, L3Order},
+    order::{OptionType, Order, OrderType},
+};
+use std::{
+    collections::{BTreeMap, VecDeque, hash_map::RandomState},
+    hash::BuildHasher,
+};
+
+/// What one winning share pays at resolution.
+pub const SHARE_PAYOUT: f64 = 10.0;
+
+/// A Yes share and a No share together always pay out `SHARE_PAYOUT`.
+pub const COMPLEMENT_CENTS: u64 = 1000;
+
+/// Aggregated `(price, quantity)` levels, best price first.
+pub type PriceLevels = Vec<(f64, u32)>;
 
 #[derive(Clone, Debug)]
 pub struct OrderBook {
     pub option: OptionType,
     pub bids: BTreeMap<u64, VecDeque<Order>>,
     pub asks: BTreeMap<u64, VecDeque<Order>>,
+    sequence: u64,
+    l3_events: Vec<L3Event>,
+    order_ref_key: RandomState,
 }
 
 impl OrderBook {
@@ -14,6 +32,9 @@ impl OrderBook {
             option,
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
+            sequence: 0,
+            l3_events: Vec::new(),
+            order_ref_key: RandomState::new(),
         }
     }
 
@@ -23,6 +44,7 @@ impl OrderBook {
 
     pub fn add_order(&mut self, order: Order) {
         println!("add order called");
+        self.record_l3(L3EventKind::Add, &order, order.quantity);
         let price_cents = Self::price_to_cents(order.price);
         let orders = match order.order_type {
             OrderType::Buy => &mut self.bids,
@@ -34,18 +56,129 @@ impl OrderBook {
             .push_back(order);
     }
 
-    pub fn remove_order(&mut self, order_type: OrderType, price: f64, order_id: u64) {
+    /// Takes an order out of the book, returning it if it was resting.
+    pub fn remove_order(
+        &mut self,
+        order_type: OrderType,
+        price: f64,
+        order_id: u64,
+    ) -> Option<Order> {
         let price_cents = Self::price_to_cents(price);
         let orders = match order_type {
             OrderType::Buy => &mut self.bids,
             OrderType::Sell => &mut self.asks,
         };
+        let mut removed = None;
         if let Some(queue) = orders.get_mut(&price_cents) {
-            queue.retain(|o| o.id != order_id);
+            if let Some(index) = queue.iter().position(|o| o.id == order_id) {
+                removed = queue.remove(index);
+            }
             if queue.is_empty() {
                 orders.remove(&price_cents);
             }
         }
+        if let Some(order) = &removed {
+            self.record_l3(L3EventKind::Cancel, order, order.quantity);
+        }
+        removed
+    }
+
+    /// Executes `quantity` of a resting order in place at `fill_price`,
+    /// keeping its queue position if anything is left. Returns the order as
+    /// it stands after the fill.
+    pub fn fill_order(
+        &mut self,
+        order_type: OrderType,
+        price: f64,
+        order_id: u64,
+        quantity: u32,
+        fill_price: f64,
+    ) -> Option<Order> {
+        let price_cents = Self::price_to_cents(price);
+        let orders = match order_type {
+            OrderType::Buy => &mut self.bids,
+            OrderType::Sell => &mut self.asks,
+        };
+        let queue = orders.get_mut(&price_cents)?;
+        let index = queue.iter().position(|o| o.id == order_id)?;
+        let order = queue[index].clone();
+        let matched = quantity.min(order.quantity);
+        let mut filled = order.clone();
+        filled.fill(matched, fill_price);
+        if filled.quantity > 0 {
+            queue[index] = filled.clone();
+        } else {
+            queue.remove(index);
+            if queue.is_empty() {
+                orders.remove(&price_cents);
+            }
+        }
+        self.record_fill(&order, matched);
+        Some(filled)
+    }
+
+    /// Records that `matched` units of the resting `order` traded. The matching
+    /// engine pops and re-queues resting orders itself, so it reports each
+    /// execution here to keep the L3 feed in step with the book.
+    pub fn record_fill(&mut self, order: &Order, matched: u32) {
+        self.record_l3(L3EventKind::Fill, order, matched);
+        if order.quantity > matched {
+            self.record_l3(L3EventKind::Modify, order, order.quantity - matched);
+        }
+    }
+
+    fn record_l3(&mut self, kind: L3EventKind, order: &Order, quantity: u32) {
+        self.sequence += 1;
+        self.l3_events.push(L3Event {
+            sequence: self.sequence,
+            kind,
+            order_ref: self.order_ref(order.id),
+            option: self.option,
+            side: order.order_type.clone(),
+            price: order.price,
+            quantity,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        });
+    }
+
+    fn order_ref(&self, order_id: u64) -> u64 {
+        self.order_ref_key.hash_one(order_id)
+    }
+
+    pub fn take_l3_events(&mut self) -> Vec<L3Event> {
+        std::mem::take(&mut self.l3_events)
+    }
+
+    pub fn get_l3_snapshot(&self) -> L3BookSnapshot {
+        let to_l3 = |order: &Order| L3Order {
+            order_ref: self.order_ref(order.id),
+            side: order.order_type.clone(),
+            price: order.price,
+            quantity: order.quantity,
+        };
+        L3BookSnapshot {
+            option: self.option,
+            sequence: self.sequence,
+            bids: self.bids.values().rev().flatten().map(to_l3).collect(),
+            asks: self.asks.values().flatten().map(to_l3).collect(),
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<f64> {
+        self.bids
+            .keys()
+            .next_back()
+            .map(|&price_cents| price_cents as f64 / 100.0)
+    }
+
+    pub fn best_ask(&self) -> Option<f64> {
+        self.asks
+            .keys()
+            .next()
+            .map(|&price_cents| price_cents as f64 / 100.0)
     }
 
     pub fn get_open_orders(&self, user_id: u32) -> Vec<Order> {
@@ -67,7 +200,47 @@ impl OrderBook {
         orders
     }
 
-    pub fn get_depth(&self) -> (Vec<(f64, u32)>, Vec<(f64, u32)>) {
+    /// Merges the Yes book with the liquidity the No book implies at the
+    /// complementary price into a single Yes-price ladder.
+    pub fn get_combined_depth(market_id: &str, yes: &OrderBook, no: &OrderBook) -> CombinedBook {
+        // price_cents -> (native, implied)
+        let mut bids: BTreeMap<u64, (u32, u32)> = BTreeMap::new();
+        let mut asks: BTreeMap<u64, (u32, u32)> = BTreeMap::new();
+        let level_quantity =
+            |queue: &VecDeque<Order>| queue.iter().map(|o| o.quantity).sum::<u32>();
+
+        for (&price_cents, queue) in &yes.bids {
+            bids.entry(price_cents).or_default().0 += level_quantity(queue);
+        }
+        for (&price_cents, queue) in &yes.asks {
+            asks.entry(price_cents).or_default().0 += level_quantity(queue);
+        }
+        for (&price_cents, queue) in &no.asks {
+            bids.entry(COMPLEMENT_CENTS - price_cents).or_default().1 += level_quantity(queue);
+        }
+        for (&price_cents, queue) in &no.bids {
+            asks.entry(COMPLEMENT_CENTS - price_cents).or_default().1 += level_quantity(queue);
+        }
+
+        let to_level = |(&price_cents, &(native, implied)): (&u64, &(u32, u32))| {
+            let price = price_cents as f64 / 100.0;
+            CombinedLevel {
+                price,
+                probability: price / SHARE_PAYOUT * 100.0,
+                native_quantity: native,
+                implied_quantity: implied,
+                total_quantity: native + implied,
+            }
+        };
+
+        CombinedBook {
+            market_id: market_id.to_string(),
+            bids: bids.iter().rev().map(to_level).collect(),
+            asks: asks.iter().map(to_level).collect(),
+        }
+    }
+
+    pub fn get_depth(&self) -> (PriceLevels, PriceLevels) {
         let mut bids = Vec::new();
         let mut asks = Vec::new();
 
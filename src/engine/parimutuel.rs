@@ -0,0 +1,189 @@
+use crate::types::{market_data::PoolSnapshot, order::OptionType};
+use std::collections::HashMap;
+
+/// Result of resolving a pool: `(user_id, option, staked, payout)` for every
+/// stake not yet paid and the commission the house keeps, zero once it has
+/// been booked.
+pub struct PoolSettlement {
+    pub payouts: Vec<(u32, OptionType, f64, f64)>,
+    pub commission: f64,
+}
+
+/// Pool betting on a binary market. Stakes sit locked in the stakers'
+/// balances until resolution, when the whole pool minus commission is paid
+/// out to the winning side pro rata to stake.
+#[derive(Clone, Debug, Default)]
+pub struct ParimutuelPool {
+    stakes: HashMap<(u32, OptionType), f64>,
+    yes_total: f64,
+    no_total: f64,
+    resolved: Option<OptionType>,
+    commission_booked: bool,
+}
+
+impl ParimutuelPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stake(&mut self, user_id: u32, option: OptionType, amount: f64) -> Result<(), String> {
+        if self.resolved.is_some() {
+            return Err("Pool already resolved".to_string());
+        }
+        if !amount.is_finite() || amount <= 0.0 {
+            return Err("Stake must be positive".to_string());
+        }
+        *self.stakes.entry((user_id, option)).or_default() += amount;
+        match option {
+            OptionType::Yes => self.yes_total += amount,
+            OptionType::No => self.no_total += amount,
+        }
+        Ok(())
+    }
+
+    /// Fixes the outcome and works out the payouts. Paid stakes are dropped
+    /// with `paid` but the totals stay as staked, so settling again with the
+    /// same outcome returns what is still owed at the same odds.
+    pub fn settle(
+        &mut self,
+        outcome: OptionType,
+        commission_rate: f64,
+    ) -> Result<PoolSettlement, String> {
+        match self.resolved {
+            Some(resolved) if resolved != outcome => {
+                return Err(format!("Pool already resolved as {:?}", resolved));
+            }
+            _ => self.resolved = Some(outcome),
+        }
+
+        let total = self.yes_total + self.no_total;
+        let winning_total = match outcome {
+            OptionType::Yes => self.yes_total,
+            OptionType::No => self.no_total,
+        };
+
+        // Nobody backed the outcome: refund every stake in full
+        if winning_total == 0.0 {
+            return Ok(PoolSettlement {
+                payouts: self
+                    .stakes
+                    .iter()
+                    .map(|(&(user_id, option), &staked)| (user_id, option, staked, staked))
+                    .collect(),
+                commission: 0.0,
+            });
+        }
+
+        let commission = total * commission_rate;
+        let distributable = total - commission;
+        let payouts = self
+            .stakes
+            .iter()
+            .map(|(&(user_id, option), &staked)| {
+                let payout = if option == outcome {
+                    distributable * staked / winning_total
+                } else {
+                    0.0
+                };
+                (user_id, option, staked, payout)
+            })
+            .collect();
+
+        Ok(PoolSettlement {
+            payouts,
+            commission: if self.commission_booked {
+                0.0
+            } else {
+                commission
+            },
+        })
+    }
+
+    /// Marks the commission of the settlement as taken by the house.
+    pub fn commission_booked(&mut self) {
+        self.commission_booked = true;
+    }
+
+    /// Drops a stake once its payout has been made.
+    pub fn paid(&mut self, user_id: u32, option: OptionType) {
+        self.stakes.remove(&(user_id, option));
+    }
+
+    /// `(user_id, amount)` still locked in the pool: every stake not yet paid
+    /// out.
+    pub fn open_stakes(&self) -> impl Iterator<Item = (u32, f64)> + '_ {
+        self.stakes
+            .iter()
+            .map(|(&(user_id, _), &amount)| (user_id, amount))
+    }
+
+    pub fn snapshot(&self, market_id: &str, commission_rate: f64) -> PoolSnapshot {
+        let total = self.yes_total + self.no_total;
+        let distributable = total * (1.0 - commission_rate);
+        let odds = |side: f64| (side > 0.0).then(|| distributable / side);
+        let probability = |side: f64| if total > 0.0 { side / total } else { 0.5 };
+
+        PoolSnapshot {
+            market_id: market_id.to_string(),
+            yes_pool: self.yes_total,
+            no_pool: self.no_total,
+            yes_probability: probability(self.yes_total),
+            no_probability: probability(self.no_total),
+            yes_odds: odds(self.yes_total),
+            no_odds: odds(self.no_total),
+            stakers: self.stakes.len(),
+            resolved: self.resolved,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn winners_split_the_pool_less_commission_pro_rata() {
+        let mut pool = ParimutuelPool::new();
+        pool.stake(1, OptionType::Yes, 30.0).unwrap();
+        pool.stake(2, OptionType::Yes, 10.0).unwrap();
+        pool.stake(3, OptionType::No, 60.0).unwrap();
+        let settlement = pool.settle(OptionType::Yes, 0.1).unwrap();
+        assert_eq!(settlement.commission, 10.0);
+
+        let mut payouts = settlement.payouts;
+        payouts.sort_by_key(|p| p.0);
+        let paid: Vec<f64> = payouts.iter().map(|p| p.3).collect();
+        assert_eq!(paid, vec![67.5, 22.5, 0.0]);
+    }
+
+    #[test]
+    fn stakes_are_refunded_when_nobody_backed_the_outcome() {
+        let mut pool = ParimutuelPool::new();
+        pool.stake(1, OptionType::Yes, 30.0).unwrap();
+        assert!(pool.stake(2, OptionType::No, 0.0).is_err());
+        let settlement = pool.settle(OptionType::No, 0.1).unwrap();
+        assert_eq!(settlement.commission, 0.0);
+        assert_eq!(settlement.payouts.len(), 1);
+        assert_eq!(settlement.payouts[0].3, 30.0);
+    }
+
+    #[test]
+    fn settling_again_returns_only_unpaid_stakes() {
+        let mut pool = ParimutuelPool::new();
+        pool.stake(1, OptionType::Yes, 30.0).unwrap();
+        pool.stake(2, OptionType::Yes, 10.0).unwrap();
+        pool.stake(3, OptionType::No, 60.0).unwrap();
+        let first = pool.settle(OptionType::Yes, 0.0).unwrap();
+        assert_eq!(first.payouts.len(), 3);
+
+        pool.paid(1, OptionType::Yes);
+        pool.commission_booked();
+        let rest = pool.settle(OptionType::Yes, 0.0).unwrap();
+        assert_eq!(rest.commission, 0.0);
+        assert_eq!(rest.payouts.len(), 2);
+        let (_, _, _, payout) = rest.payouts.iter().find(|p| p.0 == 2).unwrap();
+        assert_eq!(*payout, 25.0);
+        assert!(pool.settle(OptionType::No, 0.0).is_err());
+        assert!(pool.stake(4, OptionType::No, 1.0).is_err());
+    }
+}
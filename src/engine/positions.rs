@@ -0,0 +1,185 @@
+use crate::types::order::OptionType;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+/// One user's holding in one side of a book. The engine only lets users sell
+/// shares they hold, but `quantity` is signed and `apply` handles either
+/// direction; `avg_cost` is the average price of the open quantity.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Holding {
+    pub quantity: i64,
+    pub avg_cost: f64,
+    pub realized_pnl: f64,
+}
+
+impl Holding {
+    /// Applies a fill of `delta` shares at `price` that cost `fee` on top.
+    /// The fee counts towards the cost of what is bought and comes off the
+    /// proceeds of what is sold. Fills that reduce the position realize the
+    /// net price less `avg_cost` per share closed; anything past flat opens a
+    /// new position at the net price.
+    fn apply(&mut self, delta: i64, price: f64, fee: f64) {
+        if delta == 0 {
+            return;
+        }
+        let price = price + fee / delta as f64;
+        if self.quantity == 0 || self.quantity.signum() == delta.signum() {
+            let size = self.quantity.abs() + delta.abs();
+            self.avg_cost = (self.avg_cost * self.quantity.abs() as f64
+                + price * delta.abs() as f64)
+                / size as f64;
+            self.quantity += delta;
+            return;
+        }
+
+        let closed = delta.abs().min(self.quantity.abs());
+        self.realized_pnl +=
+            closed as f64 * (price - self.avg_cost) * self.quantity.signum() as f64;
+        self.quantity += delta;
+        if self.quantity == 0 {
+            self.avg_cost = 0.0;
+        } else if self.quantity.signum() == delta.signum() {
+            self.avg_cost = price;
+        }
+    }
+}
+
+/// Open holdings per (user, market, option), built from fills. A holding is
+/// dropped once it is flat, realized PnL and all; trade history is the
+/// record of closed positions.
+pub struct PositionTracker {
+    positions: RwLock<HashMap<(u32, String, OptionType), Holding>>,
+}
+
+impl PositionTracker {
+    pub fn new() -> Self {
+        PositionTracker {
+            positions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub async fn record_fill(
+        &self,
+        user_id: u32,
+        market_id: &str,
+        option: OptionType,
+        delta: i64,
+        price: f64,
+        fee: f64,
+    ) {
+        let mut positions = self.positions.write().await;
+        let key = (user_id, market_id.to_string(), option);
+        let holding = positions.entry(key.clone()).or_default();
+        holding.apply(delta, price, fee);
+        if holding.quantity == 0 {
+            positions.remove(&key);
+        }
+    }
+
+    pub async fn get(&self, user_id: u32, market_id: &str, option: OptionType) -> i64 {
+        self.positions
+            .read()
+            .await
+            .get(&(user_id, market_id.to_string(), option))
+            .map_or(0, |holding| holding.quantity)
+    }
+
+    /// Every open `(market_id, option, holding)` of `user_id`.
+    pub async fn for_user(&self, user_id: u32) -> Vec<(String, OptionType, Holding)> {
+        let mut held: Vec<_> = self
+            .positions
+            .read()
+            .await
+            .iter()
+            .filter(|((user, _, _), _)| *user == user_id)
+            .map(|((_, market_id, option), &holding)| (market_id.clone(), *option, holding))
+            .collect();
+        held.sort_by(|a, b| (&a.0, a.1 as u8).cmp(&(&b.0, b.1 as u8)));
+        held
+    }
+
+    /// Net shares held across all users per (market, option).
+    pub async fn net_by_market(&self) -> HashMap<(String, OptionType), i64> {
+        let mut net = HashMap::new();
+        for ((_, market_id, option), holding) in self.positions.read().await.iter() {
+            *net.entry((market_id.clone(), *option)).or_default() += holding.quantity;
+        }
+        net
+    }
+
+    /// `(user_id, option, quantity)` of every holding in a market.
+    pub async fn holdings_in(&self, market_id: &str) -> Vec<(u32, OptionType, i64)> {
+        self.positions
+            .read()
+            .await
+            .iter()
+            .filter(|((_, market, _), _)| market == market_id)
+            .map(|(&(user_id, _, option), holding)| (user_id, option, holding.quantity))
+            .collect()
+    }
+
+    /// Drops a holding once its settlement has been paid. Dropping it again
+    /// is a no-op, so a settlement that stopped part way can be rerun.
+    pub async fn settle_holding(&self, user_id: u32, market_id: &str, option: OptionType) {
+        self.positions
+            .write()
+            .await
+            .remove(&(user_id, market_id.to_string(), option));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn fills_realize_against_the_average_cost() {
+        let mut holding = Holding::default();
+        holding.apply(10, 5.0, 1.0);
+        assert_eq!(holding.quantity, 10);
+        assert!(close(holding.avg_cost, 5.1));
+
+        holding.apply(-4, 6.0, 0.0);
+        assert_eq!(holding.quantity, 6);
+        assert!(close(holding.avg_cost, 5.1));
+        assert!(close(holding.realized_pnl, 3.6));
+
+        // Selling past flat opens a short at the sale price
+        holding.apply(-10, 7.0, 0.0);
+        assert_eq!(holding.quantity, -4);
+        assert!(close(holding.avg_cost, 7.0));
+        assert!(close(holding.realized_pnl, 15.0));
+
+        holding.apply(4, 6.0, 0.0);
+        assert_eq!(holding.quantity, 0);
+        assert_eq!(holding.avg_cost, 0.0);
+        assert!(close(holding.realized_pnl, 19.0));
+    }
+
+    #[tokio::test]
+    async fn flat_holdings_are_dropped() {
+        let positions = PositionTracker::new();
+        positions
+            .record_fill(1, "m", OptionType::Yes, 3, 5.0, 0.0)
+            .await;
+        positions
+            .record_fill(1, "m", OptionType::Yes, -3, 6.0, 0.0)
+            .await;
+        assert!(positions.for_user(1).await.is_empty());
+
+        positions
+            .record_fill(1, "m", OptionType::No, 2, 4.0, 0.0)
+            .await;
+        assert_eq!(
+            positions.holdings_in("m").await,
+            vec![(1, OptionType::No, 2)]
+        );
+        positions.settle_holding(1, "m", OptionType::No).await;
+        positions.settle_holding(1, "m", OptionType::No).await;
+        assert_eq!(positions.get(1, "m", OptionType::No).await, 0);
+    }
+}
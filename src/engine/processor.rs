@@ -1,23 +1,72 @@
 use crate::{
-    engine::matching_engine::MatchingEngine, redis::manager::RedisManager,
-    types::api::MessageFromApi,
+    engine::{
+        matching_engine::MatchingEngine,
+        reconciliation::ReconcileConfig,
+        strategy::{Strategy, StrategyCommand, StrategyLimits, StrategyRunner},
+    },
+    redis::manager::RedisManager,
+    types::{
+        account::TransferKind,
+        api::{MessageFromApi, MessageToApi},
+        db::{LastIds, StoredAccounts},
+    },
 };
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+// Strategies may react to the fills and book changes their own commands
+// cause; stop after this many rounds so two strategies can't loop forever.
+const MAX_STRATEGY_ROUNDS: usize = 4;
 
 pub struct EngineProcessor {
     engine: MatchingEngine,
+    strategies: StrategyRunner,
     redis: RedisManager,
+    reconcile: ReconcileConfig,
+    last_reconcile: RwLock<Instant>,
 }
 
 impl EngineProcessor {
     pub fn new(redis: RedisManager) -> Self {
         EngineProcessor {
             engine: MatchingEngine::new(redis.clone()),
+            strategies: StrategyRunner::new(),
             redis,
+            reconcile: ReconcileConfig::default(),
+            last_reconcile: RwLock::new(Instant::now()),
         }
     }
 
+    pub fn with_reconciliation(mut self, config: ReconcileConfig) -> Self {
+        self.reconcile = config;
+        self
+    }
+
+    /// Starts every id sequence after what the store already holds.
+    pub fn with_last_ids(mut self, last: LastIds) -> Self {
+        self.engine.start_ids_after(last);
+        self
+    }
+
+    /// Reopens the accounts the store holds, before any command is taken.
+    pub async fn restore_accounts(&self, stored: StoredAccounts) -> Result<(), String> {
+        self.engine.restore_accounts(stored).await
+    }
+
+    /// Registers a strategy and opens its account if needed; it still has
+    /// to be funded with a deposit before it can buy.
+    pub async fn register_strategy(&self, strategy: Box<dyn Strategy>, limits: StrategyLimits) {
+        self.engine.open_account(strategy.user_id()).await;
+        self.strategies.register(strategy, limits).await;
+    }
+
     pub async fn run(&self) {
         loop {
+            self.engine.run_scheduled().await;
+            self.run_reconciliation().await;
+            if let Err(e) = self.engine.flush_ledger().await {
+                tracing::error!("Error saving journal: {}", e);
+            }
             match self
                 .redis
                 .pop_message::<MessageFromApi>("engine_queue")
@@ -25,9 +74,12 @@ impl EngineProcessor {
             {
                 Ok(Some(message)) => {
                     println!("message from api: {:?}", message);
+                    let client_id = message.client_id().to_string();
                     if let Err(e) = self.process(message).await {
                         tracing::error!("Error processing message: {}", e);
+                        self.reply_error(e, client_id).await;
                     }
+                    self.run_strategies().await;
                 }
                 Ok(None) => {
                     //if queue is empty
@@ -42,6 +94,101 @@ impl EngineProcessor {
         }
     }
 
+    // Answers a failed request so the caller isn't left waiting for a reply
+    // that will never come. An order the risk layer refused gets its reason
+    // code instead of a plain error; either way exactly one reply goes out
+    async fn reply_error(&self, message: String, client_id: String) {
+        let reply = match self.engine.take_rejection(&client_id).await {
+            Some(rejection) => MessageToApi::OrderRejected {
+                code: rejection.code,
+                message: rejection.message,
+                client_id,
+            },
+            None => MessageToApi::Error { message, client_id },
+        };
+        if let Err(e) = self.redis.publish_message("responses", &reply).await {
+            tracing::error!("Failed to publish error response: {}", e);
+        }
+    }
+
+    async fn run_reconciliation(&self) {
+        let interval = Duration::from_secs(self.reconcile.interval_secs);
+        {
+            let mut last = self.last_reconcile.write().await;
+            if last.elapsed() < interval {
+                return;
+            }
+            *last = Instant::now();
+        }
+        if let Err(e) = self.engine.reconcile(self.reconcile.halt_on_mismatch).await {
+            tracing::error!("Reconciliation failed: {}", e);
+        }
+    }
+
+    async fn run_strategies(&self) {
+        for _ in 0..MAX_STRATEGY_ROUNDS {
+            let events = self.engine.take_strategy_events().await;
+            if events.is_empty() || self.strategies.is_empty().await {
+                return;
+            }
+            for (index, command) in self.strategies.on_events(&events).await {
+                if let Err(e) = self.execute_strategy_command(index, command).await {
+                    tracing::warn!("Strategy command failed: {}", e);
+                }
+            }
+        }
+        // Anything left over was caused by the last round; don't let it leak
+        // into the next command's events
+        self.engine.take_strategy_events().await;
+    }
+
+    async fn execute_strategy_command(
+        &self,
+        index: usize,
+        command: StrategyCommand,
+    ) -> Result<(), String> {
+        self.strategies.check_limits(index, &command).await?;
+        let user_id = self.strategies.user_id(index).await;
+        let client_id = format!("strategy-{}", index);
+
+        match command {
+            StrategyCommand::Place {
+                market_id,
+                option,
+                order_type,
+                price,
+                quantity,
+            } => {
+                let (order, _) = self
+                    .engine
+                    .place_order(
+                        user_id, market_id, option, order_type, price, quantity, client_id,
+                    )
+                    .await?;
+                self.strategies.on_order_placed(index, &order).await;
+            }
+            StrategyCommand::Cancel { order_id } => {
+                let order = self
+                    .strategies
+                    .open_order(index, order_id)
+                    .await
+                    .ok_or("Unknown strategy order".to_string())?;
+                self.engine
+                    .cancel_order(
+                        order.market_id,
+                        order.option,
+                        order.order_type,
+                        order.price,
+                        order_id,
+                        client_id,
+                    )
+                    .await?;
+                self.strategies.on_order_cancelled(index, order_id).await;
+            }
+        }
+        Ok(())
+    }
+
     async fn process(&self, message: MessageFromApi) -> Result<(), String> {
         match message {
             MessageFromApi::CreateOrder {
@@ -89,10 +236,133 @@ impl EngineProcessor {
             MessageFromApi::CreateMarket {
                 market_id,
                 question,
+                kind,
+                outcomes,
+                range,
+                metadata,
+                trading,
+                client_id,
+            } => {
+                self.engine
+                    .create_market(
+                        market_id, question, kind, outcomes, range, *metadata, trading, client_id,
+                    )
+                    .await?;
+            }
+            MessageFromApi::GetL3Snapshot {
+                market_id,
+                client_id,
+            } => {
+                self.engine.get_l3_snapshot(market_id, client_id).await?;
+            }
+            MessageFromApi::GetCandles {
+                market_id,
+                option,
+                interval,
+                limit,
+                client_id,
+            } => {
+                self.engine
+                    .get_candles(market_id, option, interval, limit, client_id)
+                    .await?;
+            }
+            MessageFromApi::SetRiskLimits {
+                scope,
+                limits,
+                client_id,
+            } => {
+                self.engine
+                    .set_risk_limits(scope, limits, client_id)
+                    .await?;
+            }
+            MessageFromApi::Admin {
+                action,
+                operator,
+                reason,
+                client_id,
+            } => {
+                self.engine
+                    .apply_admin_action(action, operator, reason, client_id)
+                    .await?;
+            }
+            MessageFromApi::CreateAccount { user_id, client_id } => {
+                self.engine.create_account(user_id, client_id).await?;
+            }
+            MessageFromApi::Deposit {
+                user_id,
+                amount,
+                client_id,
+            } => {
+                self.engine
+                    .transfer(user_id, TransferKind::Deposit, amount, client_id)
+                    .await?;
+            }
+            MessageFromApi::Withdraw {
+                user_id,
+                amount,
+                client_id,
+            } => {
+                self.engine
+                    .transfer(user_id, TransferKind::Withdrawal, amount, client_id)
+                    .await?;
+            }
+            MessageFromApi::GetAuditLog { client_id } => {
+                self.engine.get_audit_log(client_id).await?;
+            }
+            MessageFromApi::GetLedger { user_id, client_id } => {
+                self.engine.get_ledger(user_id, client_id).await?;
+            }
+            MessageFromApi::GetBalance { user_id, client_id } => {
+                self.engine.get_balance(user_id, client_id).await?;
+            }
+            MessageFromApi::GetPositions { user_id, client_id } => {
+                self.engine.get_positions(user_id, client_id).await?;
+            }
+            MessageFromApi::GetMarkets { client_id } => {
+                self.engine.get_markets(client_id).await?;
+            }
+            MessageFromApi::GetTicker {
+                market_id,
+                client_id,
+            } => {
+                self.engine.get_tickers(market_id, client_id).await?;
+            }
+            MessageFromApi::GetCombinedDepth {
+                market_id,
+                client_id,
+            } => {
+                self.engine.get_combined_depth(market_id, client_id).await?;
+            }
+            MessageFromApi::GetAmm {
+                market_id,
+                client_id,
+            } => {
+                self.engine.get_amm(market_id, client_id).await?;
+            }
+            MessageFromApi::PlaceStake {
+                user_id,
+                market_id,
+                option,
+                amount,
+                client_id,
+            } => {
+                self.engine
+                    .place_stake(user_id, market_id, option, amount, client_id)
+                    .await?;
+            }
+            MessageFromApi::GetPool {
+                market_id,
+                client_id,
+            } => {
+                self.engine.get_pool(market_id, client_id).await?;
+            }
+            MessageFromApi::ResolveMarket {
+                market_id,
+                resolution,
                 client_id,
             } => {
                 self.engine
-                    .create_market(market_id, question, client_id)
+                    .resolve_market(market_id, resolution, client_id)
                     .await?;
             }
         }
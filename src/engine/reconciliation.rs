@@ -0,0 +1,238 @@
+use crate::{
+    engine::order_book::SHARE_PAYOUT,
+    types::{ledger::LedgerAccount, order::OptionType, reconciliation::Discrepancy},
+};
+use std::collections::{BTreeSet, HashMap};
+
+// Slack for float rounding across many postings
+const TOLERANCE: f64 = 1e-6;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ReconcileConfig {
+    pub interval_secs: u64,
+    /// Halt the markets involved in a discrepancy until an operator
+    /// resumes them.
+    pub halt_on_mismatch: bool,
+}
+
+impl Default for ReconcileConfig {
+    fn default() -> Self {
+        ReconcileConfig {
+            interval_secs: 10,
+            halt_on_mismatch: false,
+        }
+    }
+}
+
+/// Each user's `locked` must equal `expected`: Σ price × quantity over their
+/// resting buys plus their open pool stakes.
+pub fn check_locked(
+    balances: &HashMap<u32, (f64, f64)>,
+    expected: &HashMap<u32, f64>,
+) -> Vec<Discrepancy> {
+    let users: BTreeSet<u32> = balances.keys().chain(expected.keys()).copied().collect();
+    users
+        .into_iter()
+        .filter_map(|user_id| {
+            let locked = balances.get(&user_id).map_or(0.0, |b| b.1);
+            let expected = expected.get(&user_id).copied().unwrap_or(0.0);
+            ((locked - expected).abs() > TOLERANCE).then_some(Discrepancy::LockedFunds {
+                user_id,
+                locked,
+                expected,
+            })
+        })
+        .collect()
+}
+
+/// Compares each user's live balance with the one `accounts`, replayed from
+/// the journal, gives them and flags accounts left owing, then checks that
+/// the live balances, fees, clearing and the house together hold exactly
+/// what was deposited net of withdrawals.
+pub fn check_cash(
+    balances: &HashMap<u32, (f64, f64)>,
+    accounts: &HashMap<LedgerAccount, f64>,
+) -> Vec<Discrepancy> {
+    let mut rebuilt: HashMap<u32, (f64, f64)> = HashMap::new();
+    let mut house = 0.0;
+    for (&account, &amount) in accounts {
+        match account {
+            LedgerAccount::Available(user_id) => rebuilt.entry(user_id).or_default().0 += amount,
+            LedgerAccount::Locked(user_id) => rebuilt.entry(user_id).or_default().1 += amount,
+            LedgerAccount::Clearing
+            | LedgerAccount::Fees
+            | LedgerAccount::House
+            | LedgerAccount::External => house += amount,
+        }
+    }
+
+    let mut found = Vec::new();
+    let user_ids: BTreeSet<u32> = balances.keys().chain(rebuilt.keys()).copied().collect();
+    for user_id in user_ids {
+        let balance = balances.get(&user_id).copied().unwrap_or_default();
+        let replayed = rebuilt.get(&user_id).copied().unwrap_or_default();
+        if (balance.0 - replayed.0).abs() > TOLERANCE || (balance.1 - replayed.1).abs() > TOLERANCE
+        {
+            found.push(Discrepancy::Ledger {
+                user_id,
+                balance,
+                rebuilt: replayed,
+            });
+        }
+        if balance.0 < -TOLERANCE {
+            found.push(Discrepancy::NegativeBalance {
+                user_id,
+                available: balance.0,
+            });
+        }
+    }
+
+    let users_total: f64 = balances.values().map(|(a, l)| a + l).sum();
+    let imbalance = users_total + house;
+    if imbalance.abs() > TOLERANCE {
+        found.push(Discrepancy::CashNotConserved { imbalance });
+    }
+    found
+}
+
+/// Clearing must hold `SHARE_PAYOUT` for every outstanding share pair: the
+/// Yes shares held by users plus the ones the house kept for each No its
+/// AMM sold. Takes the same inputs as `check_positions`.
+pub fn check_clearing(
+    clearing: f64,
+    net: &HashMap<(String, OptionType), i64>,
+    amm_inventory: &HashMap<String, (i64, i64)>,
+) -> Vec<Discrepancy> {
+    let yes_held: i64 = net
+        .iter()
+        .filter(|((_, option), _)| *option == OptionType::Yes)
+        .map(|(_, quantity)| quantity)
+        .sum();
+    let house_yes: i64 = amm_inventory.values().map(|(_, q_no)| q_no).sum();
+    let collateral = (yes_held + house_yes) as f64 * SHARE_PAYOUT;
+    if (clearing - collateral).abs() > TOLERANCE {
+        vec![Discrepancy::Clearing {
+            clearing,
+            collateral,
+        }]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Every share is minted as half of a Yes + No pair, so per book pair the
+/// Yes shares held by users and the AMM must match the No shares. `net`
+/// sums user positions per (book, option); `amm_inventory` is each AMM's
+/// `(q_yes, q_no)` sold.
+pub fn check_positions(
+    net: &HashMap<(String, OptionType), i64>,
+    amm_inventory: &HashMap<String, (i64, i64)>,
+) -> Vec<Discrepancy> {
+    let market_ids: BTreeSet<&String> = net
+        .keys()
+        .map(|(market_id, _)| market_id)
+        .chain(amm_inventory.keys())
+        .collect();
+
+    market_ids
+        .into_iter()
+        .filter_map(|market_id| {
+            let held = |option| net.get(&(market_id.clone(), option)).copied().unwrap_or(0);
+            let (amm_yes, amm_no) = amm_inventory.get(market_id).copied().unwrap_or_default();
+            // Shares bought from the AMM have no opposite side among users;
+            // the AMM is short them instead
+            let yes = held(OptionType::Yes) - amm_yes;
+            let no = held(OptionType::No) - amm_no;
+            (yes != no).then(|| Discrepancy::Positions {
+                market_id: market_id.clone(),
+                yes,
+                no,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locked_funds_are_checked_for_every_user_either_side_knows() {
+        let balances = HashMap::from([(1, (10.0, 5.0))]);
+        let expected = HashMap::from([(1, 5.0), (2, 3.0)]);
+        let found = check_locked(&balances, &expected);
+        assert_eq!(found.len(), 1);
+        assert!(matches!(
+            found[0],
+            Discrepancy::LockedFunds { user_id: 2, locked, expected } if locked == 0.0 && expected == 3.0
+        ));
+    }
+
+    #[test]
+    fn cash_balances_against_the_ledger_totals() {
+        let balances = HashMap::from([(1, (90.0, 5.0))]);
+        let accounts = HashMap::from([
+            (LedgerAccount::External, -100.0),
+            (LedgerAccount::Available(1), 90.0),
+            (LedgerAccount::Locked(1), 5.0),
+            (LedgerAccount::Fees, 5.0),
+        ]);
+        assert!(check_cash(&balances, &accounts).is_empty());
+
+        // A payout the ledger never saw
+        let balances = HashMap::from([(1, (91.0, 5.0))]);
+        let found = check_cash(&balances, &accounts);
+        assert!(matches!(found[0], Discrepancy::Ledger { user_id: 1, .. }));
+        assert!(
+            matches!(found[1], Discrepancy::CashNotConserved { imbalance } if imbalance == 1.0)
+        );
+    }
+
+    #[test]
+    fn an_account_left_owing_is_flagged() {
+        let balances = HashMap::from([(1, (-2.0, 0.0))]);
+        let accounts = HashMap::from([
+            (LedgerAccount::Available(1), -2.0),
+            (LedgerAccount::Clearing, 2.0),
+        ]);
+        let found = check_cash(&balances, &accounts);
+        assert_eq!(found.len(), 1);
+        assert!(matches!(
+            found[0],
+            Discrepancy::NegativeBalance { user_id: 1, available } if available == -2.0
+        ));
+    }
+
+    #[test]
+    fn clearing_holds_a_payout_per_share_pair() {
+        let net = HashMap::from([
+            (("m".to_string(), OptionType::Yes), 5),
+            (("m".to_string(), OptionType::No), 3),
+        ]);
+        let amm = HashMap::from([("m".to_string(), (2, 1))]);
+        assert!(check_clearing(60.0, &net, &amm).is_empty());
+
+        // Two sellers credited for shares neither held
+        let found = check_clearing(50.0, &net, &amm);
+        assert!(matches!(
+            found[..],
+            [Discrepancy::Clearing { clearing, collateral }] if clearing == 50.0 && collateral == 60.0
+        ));
+    }
+
+    #[test]
+    fn shares_sold_by_the_amm_count_against_its_side() {
+        let net = HashMap::from([
+            (("m".to_string(), OptionType::Yes), 5),
+            (("m".to_string(), OptionType::No), 3),
+            (("n".to_string(), OptionType::Yes), 1),
+        ]);
+        let amm = HashMap::from([("m".to_string(), (2, 0))]);
+        let found = check_positions(&net, &amm);
+        assert_eq!(found.len(), 1);
+        assert!(matches!(
+            &found[0],
+            Discrepancy::Positions { market_id, yes: 1, no: 0 } if market_id == "n"
+        ));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::types::{
+    order::{OptionType, OrderType},
+    risk::{RejectCode, RiskLimits, RiskScope},
+};
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+/// What the risk checks need to know about an incoming order and its owner.
+pub struct OrderExposure {
+    pub option: OptionType,
+    pub order_type: OrderType,
+    pub price: f64,
+    pub quantity: u32,
+    /// The user's resting orders across all markets.
+    pub open_orders: usize,
+    /// Net Yes-equivalent shares already held in the order's market: Yes
+    /// held less No held.
+    pub position: i64,
+    /// Signed Yes-equivalent quantity of the user's open orders in the
+    /// order's market that move the position the same way it does.
+    pub pending: i64,
+    pub last_price: Option<f64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RiskRejection {
+    pub code: RejectCode,
+    pub message: String,
+}
+
+/// Global, per-market and per-user pre-trade limits.
+pub struct RiskManager {
+    global: RwLock<RiskLimits>,
+    markets: RwLock<HashMap<String, RiskLimits>>,
+    users: RwLock<HashMap<u32, RiskLimits>>,
+}
+
+impl RiskManager {
+    pub fn new() -> Self {
+        RiskManager {
+            global: RwLock::new(RiskLimits {
+                max_order_quantity: Some(10_000),
+                max_notional: Some(50_000.0),
+                max_open_orders: Some(200),
+                max_position: Some(50_000),
+                price_collar: Some(3.0),
+            }),
+            markets: RwLock::new(HashMap::new()),
+            users: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub async fn set_limits(&self, scope: RiskScope, limits: RiskLimits) {
+        match scope {
+            RiskScope::Global => *self.global.write().await = limits,
+            RiskScope::Market(market_id) => {
+                self.markets.write().await.insert(market_id, limits);
+            }
+            RiskScope::User(user_id) => {
+                self.users.write().await.insert(user_id, limits);
+            }
+        }
+    }
+
+    /// Limits in force for `user_id` trading `market_id`: the strictest of
+    /// the global, market and user limits, field by field. Outcome books of
+    /// a multi-outcome market inherit the parent market's limits.
+    pub async fn limits_for(&self, user_id: u32, market_id: &str) -> RiskLimits {
+        let markets = self.markets.read().await;
+        let market = markets
+            .get(market_id)
+            .or_else(|| {
+                market_id
+                    .split_once(':')
+                    .and_then(|(group_id, _)| markets.get(group_id))
+            })
+            .copied()
+            .unwrap_or_default();
+        let user = self
+            .users
+            .read()
+            .await
+            .get(&user_id)
+            .copied()
+            .unwrap_or_default();
+        user.stricter(market).stricter(*self.global.read().await)
+    }
+
+    pub fn check(limits: &RiskLimits, order: &OrderExposure) -> Result<(), RiskRejection> {
+        let reject = |code, message: String| Err(RiskRejection { code, message });
+
+        if let Some(max) = limits.max_order_quantity
+            && order.quantity > max
+        {
+            return reject(
+                RejectCode::MaxOrderQuantity,
+                format!("Order quantity {} exceeds limit {}", order.quantity, max),
+            );
+        }
+        let notional = order.price * order.quantity as f64;
+        if let Some(max) = limits.max_notional
+            && notional > max
+        {
+            return reject(
+                RejectCode::MaxNotional,
+                format!("Order notional {} exceeds limit {}", notional, max),
+            );
+        }
+        if let Some(max) = limits.max_open_orders
+            && order.open_orders >= max
+        {
+            return reject(
+                RejectCode::MaxOpenOrders,
+                format!("Open order limit {} reached", max),
+            );
+        }
+        // Only an order that adds to the exposure is held to the limit, so a
+        // position already past it can still be worked down
+        let exposure = order.position + order.pending;
+        let delta = yes_direction(order.option, &order.order_type) * order.quantity as i64;
+        let worst = exposure + delta;
+        if let Some(max) = limits.max_position
+            && worst.abs() > max
+            && worst.abs() > exposure.abs()
+        {
+            return reject(
+                RejectCode::MaxPosition,
+                format!("Position {} would exceed limit {}", worst, max),
+            );
+        }
+        if let (Some(collar), Some(last_price)) = (limits.price_collar, order.last_price)
+            && (order.price - last_price).abs() > collar
+        {
+            return reject(
+                RejectCode::PriceCollar,
+                format!(
+                    "Price {} is more than {} from the last trade {}",
+                    order.price, collar, last_price
+                ),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// +1 if an order on `option` and `order_type` adds Yes exposure, -1 if it
+/// takes it away: buying No is selling Yes.
+pub fn yes_direction(option: OptionType, order_type: &OrderType) -> i64 {
+    match (option, order_type) {
+        (OptionType::Yes, OrderType::Buy) | (OptionType::No, OrderType::Sell) => 1,
+        (OptionType::Yes, OrderType::Sell) | (OptionType::No, OrderType::Buy) => -1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exposure(option: OptionType, order_type: OrderType, quantity: u32) -> OrderExposure {
+        OrderExposure {
+            option,
+            order_type,
+            price: 5.0,
+            quantity,
+            open_orders: 0,
+            position: 0,
+            pending: 0,
+            last_price: None,
+        }
+    }
+
+    fn rejected(limits: RiskLimits, order: &OrderExposure) -> Option<RejectCode> {
+        RiskManager::check(&limits, order).err().map(|r| r.code)
+    }
+
+    #[test]
+    fn position_counts_pending_orders_and_no_as_short_yes() {
+        let limits = RiskLimits {
+            max_position: Some(10),
+            ..RiskLimits::default()
+        };
+        let mut order = exposure(OptionType::No, OrderType::Buy, 5);
+        order.position = -3;
+        order.pending = -4;
+        assert_eq!(rejected(limits, &order), Some(RejectCode::MaxPosition));
+
+        // Selling No reduces the short
+        order.order_type = OrderType::Sell;
+        assert_eq!(rejected(limits, &order), None);
+
+        // Past the limit already, an order that shrinks the position passes
+        let mut order = exposure(OptionType::Yes, OrderType::Sell, 5);
+        order.position = 20;
+        assert_eq!(rejected(limits, &order), None);
+        order.order_type = OrderType::Buy;
+        assert_eq!(rejected(limits, &order), Some(RejectCode::MaxPosition));
+    }
+
+    #[test]
+    fn each_limit_is_enforced() {
+        let limits = RiskLimits {
+            max_order_quantity: Some(100),
+            max_notional: Some(400.0),
+            max_open_orders: Some(2),
+            max_position: None,
+            price_collar: Some(1.0),
+        };
+        let order = exposure(OptionType::Yes, OrderType::Buy, 101);
+        assert_eq!(rejected(limits, &order), Some(RejectCode::MaxOrderQuantity));
+        let order = exposure(OptionType::Yes, OrderType::Buy, 90);
+        assert_eq!(rejected(limits, &order), Some(RejectCode::MaxNotional));
+
+        let mut order = exposure(OptionType::Yes, OrderType::Buy, 10);
+        order.open_orders = 2;
+        assert_eq!(rejected(limits, &order), Some(RejectCode::MaxOpenOrders));
+        order.open_orders = 1;
+        order.last_price = Some(6.5);
+        assert_eq!(rejected(limits, &order), Some(RejectCode::PriceCollar));
+        order.last_price = Some(6.0);
+        assert_eq!(rejected(limits, &order), None);
+        assert_eq!(rejected(RiskLimits::default(), &order), None);
+    }
+
+    #[tokio::test]
+    async fn outcome_books_take_the_strictest_of_user_group_and_global_limits() {
+        let risk = RiskManager::new();
+        let group = RiskLimits {
+            max_order_quantity: Some(50),
+            max_notional: Some(100.0),
+            ..RiskLimits::default()
+        };
+        let user = RiskLimits {
+            max_order_quantity: Some(20),
+            max_notional: Some(500.0),
+            max_open_orders: Some(1_000),
+            ..RiskLimits::default()
+        };
+        risk.set_limits(RiskScope::Market("g".to_string()), group)
+            .await;
+        risk.set_limits(RiskScope::User(1), user).await;
+
+        let limits = risk.limits_for(1, "g:a").await;
+        assert_eq!(limits.max_order_quantity, Some(20));
+        assert_eq!(limits.max_notional, Some(100.0));
+        // An override looser than the global limit doesn't lift it
+        assert_eq!(limits.max_open_orders, Some(200));
+        assert_eq!(limits.max_position, Some(50_000));
+        assert_eq!(
+            risk.limits_for(2, "h").await.max_order_quantity,
+            Some(10_000)
+        );
+    }
+}
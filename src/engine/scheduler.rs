@@ -0,0 +1,118 @@
+use std::collections::{BTreeSet, HashMap};
+use tokio::sync::RwLock;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ScheduledAction {
+    /// Stop trading at the market's close time.
+    Close,
+    /// End a call auction and resume continuous trading.
+    Uncross,
+    /// Lift a circuit-breaker halt.
+    Resume,
+}
+
+impl ScheduledAction {
+    // Uncross and Resume end the phase they were scheduled in; a close
+    // holds whatever phase the market is in by then
+    fn ends_phase(self) -> bool {
+        !matches!(self, ScheduledAction::Close)
+    }
+}
+
+/// Timed market transitions, earliest first. The engine processor polls it
+/// between messages and applies every action whose time has come.
+pub struct MarketScheduler {
+    // (time in ms, market id, action, phase it was scheduled in)
+    schedule: RwLock<BTreeSet<(u64, String, ScheduledAction, u64)>>,
+    // Bumped on every status change of a market, so an action left over
+    // from an earlier auction or halt can be told apart and dropped
+    phases: RwLock<HashMap<String, u64>>,
+}
+
+impl MarketScheduler {
+    pub fn new() -> Self {
+        MarketScheduler {
+            schedule: RwLock::new(BTreeSet::new()),
+            phases: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Records that `market_id` changed status. Uncross and resume actions
+    /// scheduled before this no longer apply.
+    pub async fn next_phase(&self, market_id: &str) {
+        *self
+            .phases
+            .write()
+            .await
+            .entry(market_id.to_string())
+            .or_default() += 1;
+    }
+
+    async fn phase(&self, market_id: &str) -> u64 {
+        self.phases
+            .read()
+            .await
+            .get(market_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Schedules `action` for `market_id` at `at_ms` (unix milliseconds),
+    /// tied to the market's current phase.
+    pub async fn schedule(&self, market_id: &str, action: ScheduledAction, at_ms: u64) {
+        let phase = self.phase(market_id).await;
+        self.schedule
+            .write()
+            .await
+            .insert((at_ms, market_id.to_string(), action, phase));
+    }
+
+    /// Removes and returns the actions due at `now_ms`, in time order.
+    /// Uncross and resume actions whose phase has since ended are dropped.
+    pub async fn take_due(&self, now_ms: u64) -> Vec<(String, ScheduledAction)> {
+        let is_due = |entry: Option<&(u64, String, ScheduledAction, u64)>| {
+            entry.is_some_and(|(at_ms, _, _, _)| *at_ms <= now_ms)
+        };
+        if !is_due(self.schedule.read().await.first()) {
+            return Vec::new();
+        }
+
+        let mut schedule = self.schedule.write().await;
+        let phases = self.phases.read().await;
+        let mut due = Vec::new();
+        while is_due(schedule.first()) {
+            if let Some((_, market_id, action, phase)) = schedule.pop_first() {
+                let current = phases.get(&market_id).copied().unwrap_or_default();
+                if !action.ends_phase() || phase == current {
+                    due.push((market_id, action));
+                }
+            }
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn actions_from_an_earlier_phase_are_dropped() {
+        let scheduler = MarketScheduler::new();
+        scheduler.schedule("m", ScheduledAction::Close, 50).await;
+        scheduler.next_phase("m").await;
+        scheduler.schedule("m", ScheduledAction::Resume, 10).await;
+        // Resumed by hand, then into an auction of its own
+        scheduler.next_phase("m").await;
+        scheduler.schedule("m", ScheduledAction::Uncross, 20).await;
+
+        assert!(scheduler.take_due(5).await.is_empty());
+        assert_eq!(
+            scheduler.take_due(60).await,
+            vec![
+                ("m".to_string(), ScheduledAction::Uncross),
+                ("m".to_string(), ScheduledAction::Close),
+            ]
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use crate::{
+    engine::order_book::{COMPLEMENT_CENTS, OrderBook, SHARE_PAYOUT},
+    types::{
+        market_data::{CombinedBook, CombinedLevel},
+        order::{OptionType, Order, OrderType, Trade},
+    },
+};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::Mutex;
+
+/// Market activity the engine hands to in-process strategies.
+#[derive(Clone, Debug)]
+pub enum StrategyEvent {
+    /// The market's Yes-price ladder, No liquidity included at the
+    /// complementary price.
+    Book {
+        market_id: String,
+        book: CombinedBook,
+    },
+    Trade(Trade),
+}
+
+#[derive(Clone, Debug)]
+pub enum StrategyCommand {
+    Place {
+        market_id: String,
+        option: OptionType,
+        order_type: OrderType,
+        price: f64,
+        quantity: u32,
+    },
+    Cancel {
+        order_id: u64,
+    },
+}
+
+/// A quoting strategy running inside the engine process. It only sees events
+/// and its own orders; every command it returns goes through the engine's
+/// normal order path under the strategy's `user_id`.
+pub trait Strategy: Send {
+    fn name(&self) -> &str;
+    fn user_id(&self) -> u32;
+    fn on_event(&mut self, event: &StrategyEvent, open_orders: &[Order]) -> Vec<StrategyCommand>;
+}
+
+#[derive(Clone, Debug)]
+pub struct StrategyLimits {
+    pub max_order_quantity: u32,
+    pub max_open_orders: usize,
+    /// Largest net Yes-minus-No share position per market, assuming every
+    /// open order fills.
+    pub max_position: i64,
+}
+
+struct StrategySlot {
+    strategy: Box<dyn Strategy>,
+    limits: StrategyLimits,
+    open_orders: HashMap<u64, Order>,
+    // Every order id the strategy ever placed, to attribute fills
+    own_order_ids: HashSet<u64>,
+    // market_id -> net shares, Yes positive and No negative
+    positions: HashMap<String, i64>,
+}
+
+impl StrategySlot {
+    fn signed(option: OptionType, order_type: &OrderType, quantity: u32) -> i64 {
+        let quantity = quantity as i64;
+        match (option, order_type) {
+            (OptionType::Yes, OrderType::Buy) | (OptionType::No, OrderType::Sell) => quantity,
+            (OptionType::Yes, OrderType::Sell) | (OptionType::No, OrderType::Buy) => -quantity,
+        }
+    }
+
+    fn check_limits(&self, command: &StrategyCommand) -> Result<(), String> {
+        let StrategyCommand::Place {
+            market_id,
+            option,
+            order_type,
+            quantity,
+            ..
+        } = command
+        else {
+            return Ok(());
+        };
+
+        if *quantity > self.limits.max_order_quantity {
+            return Err(format!(
+                "order quantity {} exceeds limit {}",
+                quantity, self.limits.max_order_quantity
+            ));
+        }
+        if self.open_orders.len() >= self.limits.max_open_orders {
+            return Err(format!(
+                "open order limit {} reached",
+                self.limits.max_open_orders
+            ));
+        }
+
+        let delta = Self::signed(*option, order_type, *quantity);
+        let exposure: i64 = self
+            .open_orders
+            .values()
+            .filter(|o| &o.market_id == market_id)
+            .map(|o| Self::signed(o.option, &o.order_type, o.quantity))
+            .filter(|q| q.signum() == delta.signum())
+            .sum();
+        let worst = self.positions.get(market_id).copied().unwrap_or(0) + exposure + delta;
+        if worst.abs() > self.limits.max_position {
+            return Err(format!(
+                "position {} would exceed limit {}",
+                worst, self.limits.max_position
+            ));
+        }
+        Ok(())
+    }
+
+    fn apply_trade(&mut self, trade: &Trade) {
+        for (order_id, order_type) in [
+            (trade.buy_order_id, OrderType::Buy),
+            (trade.sell_order_id, OrderType::Sell),
+        ] {
+            if !self.own_order_ids.contains(&order_id) {
+                continue;
+            }
+            *self.positions.entry(trade.market_id.clone()).or_default() +=
+                Self::signed(trade.option, &order_type, trade.quantity);
+            if let Some(order) = self.open_orders.get_mut(&order_id) {
+                order.quantity = order.quantity.saturating_sub(trade.quantity);
+                if order.quantity == 0 {
+                    self.open_orders.remove(&order_id);
+                }
+            }
+        }
+    }
+}
+
+/// Hosts the registered strategies, feeds them engine events and enforces
+/// each strategy's risk limits on the commands they emit.
+pub struct StrategyRunner {
+    slots: Mutex<Vec<StrategySlot>>,
+}
+
+impl StrategyRunner {
+    pub fn new() -> Self {
+        StrategyRunner {
+            slots: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub async fn register(&self, strategy: Box<dyn Strategy>, limits: StrategyLimits) {
+        tracing::info!("Registered strategy {}", strategy.name());
+        self.slots.lock().await.push(StrategySlot {
+            strategy,
+            limits,
+            open_orders: HashMap::new(),
+            own_order_ids: HashSet::new(),
+            positions: HashMap::new(),
+        });
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.slots.lock().await.is_empty()
+    }
+
+    /// Delivers events to every strategy and returns the commands they
+    /// emitted, tagged with the index of the emitting strategy.
+    pub async fn on_events(&self, events: &[StrategyEvent]) -> Vec<(usize, StrategyCommand)> {
+        let mut slots = self.slots.lock().await;
+        let mut commands = Vec::new();
+        for (index, slot) in slots.iter_mut().enumerate() {
+            for event in events {
+                if let StrategyEvent::Trade(trade) = event {
+                    slot.apply_trade(trade);
+                }
+                let open_orders: Vec<Order> = slot.open_orders.values().cloned().collect();
+                for command in slot.strategy.on_event(event, &open_orders) {
+                    commands.push((index, command));
+                }
+            }
+        }
+        commands
+    }
+
+    /// Checks a command against the strategy's limits as they stand right
+    /// before it is executed, so earlier cancels in the same batch count.
+    pub async fn check_limits(
+        &self,
+        index: usize,
+        command: &StrategyCommand,
+    ) -> Result<(), String> {
+        let slots = self.slots.lock().await;
+        let slot = &slots[index];
+        slot.check_limits(command).map_err(|reason| {
+            format!(
+                "Strategy {} command rejected: {}",
+                slot.strategy.name(),
+                reason
+            )
+        })
+    }
+
+    pub async fn user_id(&self, index: usize) -> u32 {
+        self.slots.lock().await[index].strategy.user_id()
+    }
+
+    pub async fn open_order(&self, index: usize, order_id: u64) -> Option<Order> {
+        self.slots.lock().await[index]
+            .open_orders
+            .get(&order_id)
+            .cloned()
+    }
+
+    /// Records the engine's answer to a place command from strategy `index`.
+    pub async fn on_order_placed(&self, index: usize, order: &Order) {
+        let mut slots = self.slots.lock().await;
+        let slot = &mut slots[index];
+        slot.own_order_ids.insert(order.id);
+        if order.quantity > 0 {
+            slot.open_orders.insert(order.id, order.clone());
+        }
+    }
+
+    pub async fn on_order_cancelled(&self, index: usize, order_id: u64) {
+        self.slots.lock().await[index].open_orders.remove(&order_id);
+    }
+}
+
+/// Reference maker: keeps one bid and one ask around the mid of a market's
+/// Yes book, `spread` apart, and re-quotes whenever the mid moves. The ask
+/// is quoted as a No bid at the complement.
+pub struct SymmetricSpreadMaker {
+    name: String,
+    user_id: u32,
+    market_id: String,
+    spread: f64,
+    size: u32,
+    quoted_mid: Option<f64>,
+}
+
+impl SymmetricSpreadMaker {
+    pub fn new(user_id: u32, market_id: String, spread: f64, size: u32) -> Self {
+        SymmetricSpreadMaker {
+            name: format!("symmetric-spread:{}", market_id),
+            user_id,
+            market_id,
+            spread,
+            size,
+            quoted_mid: None,
+        }
+    }
+
+    fn mid(&self, book: &CombinedBook, open_orders: &[Order]) -> f64 {
+        // Ignore our own quotes when estimating the fair value: take the
+        // best level with anything left once our open orders, known by id,
+        // are taken out of it
+        let own = |order_type: OrderType, level: &CombinedLevel| -> u32 {
+            let cents = OrderBook::price_to_cents(level.price);
+            open_orders
+                .iter()
+                .filter(|o| o.market_id == book.market_id)
+                .filter(|o| match o.option {
+                    OptionType::Yes => {
+                        o.order_type == order_type && OrderBook::price_to_cents(o.price) == cents
+                    }
+                    // A No ask at q is a Yes bid at 10 - q and a No bid a Yes ask
+                    OptionType::No => {
+                        o.order_type != order_type
+                            && COMPLEMENT_CENTS - OrderBook::price_to_cents(o.price) == cents
+                    }
+                })
+                .map(|o| o.quantity)
+                .sum()
+        };
+        let best = |levels: &[CombinedLevel], order_type: OrderType| {
+            levels
+                .iter()
+                .find(|level| level.total_quantity > own(order_type.clone(), level))
+                .map(|level| level.price)
+        };
+        let bid = best(&book.bids, OrderType::Buy);
+        let ask = best(&book.asks, OrderType::Sell);
+        match (bid, ask) {
+            (Some(bid), Some(ask)) => (bid + ask) / 2.0,
+            (Some(bid), None) => bid + self.spread / 2.0,
+            (None, Some(ask)) => ask - self.spread / 2.0,
+            (None, None) => self.quoted_mid.unwrap_or(5.0),
+        }
+    }
+}
+
+impl Strategy for SymmetricSpreadMaker {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn user_id(&self) -> u32 {
+        self.user_id
+    }
+
+    fn on_event(&mut self, event: &StrategyEvent, open_orders: &[Order]) -> Vec<StrategyCommand> {
+        let StrategyEvent::Book { market_id, book } = event else {
+            return Vec::new();
+        };
+        if market_id != &self.market_id {
+            return Vec::new();
+        }
+
+        let mid = (self.mid(book, open_orders) * 100.0).round() / 100.0;
+        if self.quoted_mid == Some(mid) && open_orders.len() == 2 {
+            return Vec::new();
+        }
+        self.quoted_mid = Some(mid);
+
+        let mut commands: Vec<StrategyCommand> = open_orders
+            .iter()
+            .map(|o| StrategyCommand::Cancel { order_id: o.id })
+            .collect();
+        let half = self.spread / 2.0;
+        let quote = |price: f64| ((price * 100.0).round() / 100.0).clamp(0.5, 9.5);
+        // Selling Yes needs Yes shares in hand, so the ask goes out as the
+        // No bid at its complement, which mints instead
+        for (option, price) in [
+            (OptionType::Yes, quote(mid - half)),
+            (OptionType::No, quote(SHARE_PAYOUT - (mid + half))),
+        ] {
+            commands.push(StrategyCommand::Place {
+                market_id: self.market_id.clone(),
+                option,
+                order_type: OrderType::Buy,
+                price,
+                quantity: self.size,
+            });
+        }
+        commands
+    }
+}
@@ -0,0 +1,127 @@
+use crate::types::{
+    market_data::{OptionTicker, Ticker},
+    order::{OptionType, Trade},
+};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::RwLock;
+
+const WINDOW_SECS: u64 = 24 * 60 * 60;
+
+/// Best `(bid, ask)` of one book.
+pub type Quote = (Option<f64>, Option<f64>);
+
+#[derive(Default)]
+struct OptionStats {
+    last_price: Option<f64>,
+    quote: Quote,
+    // (timestamp, price, quantity) of every trade inside the window
+    window: VecDeque<(u64, f64, u32)>,
+    // Last price traded before the window started, used for the 24h change
+    reference_price: Option<f64>,
+}
+
+impl OptionStats {
+    fn record_trade(&mut self, trade: &Trade) {
+        self.last_price = Some(trade.price);
+        self.window
+            .push_back((trade.timestamp, trade.price, trade.quantity));
+    }
+
+    fn expire(&mut self, now: u64) {
+        while let Some(&(timestamp, price, _)) = self.window.front() {
+            if timestamp + WINDOW_SECS > now {
+                break;
+            }
+            self.reference_price = Some(price);
+            self.window.pop_front();
+        }
+    }
+
+    fn snapshot(&self) -> OptionTicker {
+        let prices = self.window.iter().map(|&(_, price, _)| price);
+        let open_price = self
+            .reference_price
+            .or_else(|| self.window.front().map(|&(_, price, _)| price));
+
+        OptionTicker {
+            last_price: self.last_price,
+            best_bid: self.quote.0,
+            best_ask: self.quote.1,
+            high_24h: prices.clone().reduce(f64::max),
+            low_24h: prices.reduce(f64::min),
+            price_change_24h: self
+                .last_price
+                .zip(open_price)
+                .map(|(last, open)| last - open),
+            volume_24h: self.window.iter().map(|&(_, _, qty)| qty as u64).sum(),
+            trade_count_24h: self.window.len() as u32,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MarketStats {
+    yes: OptionStats,
+    no: OptionStats,
+}
+
+impl MarketStats {
+    fn option_mut(&mut self, option: OptionType) -> &mut OptionStats {
+        match option {
+            OptionType::Yes => &mut self.yes,
+            OptionType::No => &mut self.no,
+        }
+    }
+}
+
+pub struct TickerTracker {
+    markets: RwLock<HashMap<String, MarketStats>>,
+}
+
+impl TickerTracker {
+    pub fn new() -> Self {
+        TickerTracker {
+            markets: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Applies the fills and resulting top of book of one engine event and
+    /// returns the refreshed ticker.
+    pub async fn update(
+        &self,
+        market_id: &str,
+        trades: &[Trade],
+        yes_quote: Quote,
+        no_quote: Quote,
+    ) -> Ticker {
+        let mut markets = self.markets.write().await;
+        let stats = markets.entry(market_id.to_string()).or_default();
+        for trade in trades {
+            stats.option_mut(trade.option).record_trade(trade);
+        }
+        stats.yes.quote = yes_quote;
+        stats.no.quote = no_quote;
+        Self::snapshot(market_id, stats)
+    }
+
+    pub async fn get_ticker(&self, market_id: &str) -> Ticker {
+        let mut markets = self.markets.write().await;
+        let stats = markets.entry(market_id.to_string()).or_default();
+        Self::snapshot(market_id, stats)
+    }
+
+    fn snapshot(market_id: &str, stats: &mut MarketStats) -> Ticker {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        stats.yes.expire(now);
+        stats.no.expire(now);
+        Ticker {
+            market_id: market_id.to_string(),
+            yes: stats.yes.snapshot(),
+            no: stats.no.snapshot(),
+            updated_at: now,
+        }
+    }
+}
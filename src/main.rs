@@ -1,6 +1,13 @@
 use crate::{
-    api::server::run_api_server, db::processor::DbProcessor, engine::processor::EngineProcessor,
-    redis::manager::RedisManager, ws::server::run_ws_server,
+    api::server::run_api_server,
+    db::{processor::DbProcessor, storage::StorageBackend},
+    engine::{
+        processor::EngineProcessor,
+        reconciliation::ReconcileConfig,
+        strategy::{StrategyLimits, SymmetricSpreadMaker},
+    },
+    redis::manager::RedisManager,
+    ws::server::run_ws_server,
 };
 mod api;
 mod db;
@@ -15,8 +22,63 @@ async fn main() {
     let redis = RedisManager::new("redis://127.0.0.1/");
 
     // Create all the processors and servers
-    let engine_processor = EngineProcessor::new(redis.clone());
-    let db_processor = DbProcessor::new(redis.clone());
+    let reconcile = ReconcileConfig {
+        interval_secs: std::env::var("RECONCILE_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(ReconcileConfig::default().interval_secs),
+        halt_on_mismatch: std::env::var("RECONCILE_HALT").is_ok_and(|v| v == "1" || v == "true"),
+    };
+
+    // DB_BACKEND=memory|jsonl|sqlite, defaulting to the most durable one built
+    let backend = match std::env::var("DB_BACKEND") {
+        Ok(name) => name.parse().expect("Invalid DB_BACKEND"),
+        Err(_) => StorageBackend::default(),
+    };
+    let db_path = std::env::var("DB_PATH").unwrap_or(backend.default_path().to_string());
+    let store = backend.open(&db_path).expect("Failed to open database");
+    let db_processor = DbProcessor::new(redis.clone(), store);
+    // Writes queued before a restart land first, so the ids the store reports
+    // are the last ones the engine handed out
+    db_processor
+        .catch_up()
+        .await
+        .expect("Failed to apply queued DB writes");
+    let last_ids = db_processor
+        .last_ids()
+        .expect("Failed to read the last stored ids");
+    let accounts = db_processor
+        .accounts()
+        .expect("Failed to read the stored accounts");
+    let engine_processor = EngineProcessor::new(redis.clone())
+        .with_reconciliation(reconcile)
+        .with_last_ids(last_ids);
+    engine_processor
+        .restore_accounts(accounts)
+        .await
+        .expect("Failed to restore accounts");
+
+    // Optional in-process market maker, e.g. MM_MARKET_ID=ipl-final
+    if let Ok(market_id) = std::env::var("MM_MARKET_ID") {
+        let env_or = |key: &str, default: &str| std::env::var(key).unwrap_or(default.to_string());
+        let user_id = env_or("MM_USER_ID", "9000").parse().unwrap_or(9000);
+        let spread = env_or("MM_SPREAD", "0.4").parse().unwrap_or(0.4);
+        let size = env_or("MM_SIZE", "10").parse().unwrap_or(10);
+        engine_processor
+            .register_strategy(
+                Box::new(SymmetricSpreadMaker::new(user_id, market_id, spread, size)),
+                StrategyLimits {
+                    max_order_quantity: size,
+                    max_open_orders: 2,
+                    max_position: env_or("MM_MAX_POSITION", "200").parse().unwrap_or(200),
+                },
+            )
+            .await;
+    }
+
+    // Operator endpoints (/admin, /audit, /risk_limits, /resolve, /deposit,
+    // /ledger) stay closed unless ADMIN_TOKEN is set
+    let admin_token = std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty());
 
     // Spawning the engine processor in a separate task
     let engine_handle = tokio::spawn(async move {
@@ -32,7 +94,7 @@ async fn main() {
     // Use tokio::select! to handle multiple concurrent futures
     tokio::select! {
         // Run the API server directly (not in a separate tokio task)
-        api_result = run_api_server(redis.clone(), "0.0.0.0:8000") => {
+        api_result = run_api_server(redis.clone(), "0.0.0.0:8000", admin_token) => {
             if let Err(e) = api_result {
                 tracing::error!("API server error: {}", e);
             }
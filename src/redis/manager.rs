@@ -2,9 +2,27 @@ use redis::{AsyncCommands, Client, aio::MultiplexedConnection, aio::PubSub};
 use serde::{Serialize, de::DeserializeOwned};
 use std::sync::Arc;
 
+/// Messages claimed off a queue. Payloads that no longer deserialize are
+/// handed back as they were stored, so the consumer can set them aside
+/// before it acknowledges the batch.
+pub struct Claimed<T> {
+    pub messages: Vec<T>,
+    pub unreadable: Vec<String>,
+}
+
+impl<T> Claimed<T> {
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty() && self.unreadable.is_empty()
+    }
+}
+
 #[derive(Clone)]
 pub struct RedisManager {
     client: Arc<Client>,
+    // Set for tests that drive the engine without a server: pushes and
+    // publishes are dropped
+    #[cfg(test)]
+    offline: bool,
 }
 
 impl RedisManager {
@@ -12,6 +30,16 @@ impl RedisManager {
         let client = Client::open(redis_url).expect("Failed to connect to Redis");
         Self {
             client: Arc::new(client),
+            #[cfg(test)]
+            offline: false,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn offline() -> Self {
+        Self {
+            offline: true,
+            ..Self::new("redis://127.0.0.1/")
         }
     }
 
@@ -25,14 +53,23 @@ impl RedisManager {
         message: &T,
     ) -> Result<(), redis::RedisError> {
         let serialized = serde_json::to_string(message).expect("Failed to serialize message");
+        self.push_raw(queue, &serialized).await
+    }
+
+    /// Pushes a payload that is already serialized.
+    pub async fn push_raw(&self, queue: &str, serialized: &str) -> Result<(), redis::RedisError> {
+        #[cfg(test)]
+        if self.offline {
+            return Ok(());
+        }
         let mut conn = self.get_conn().await?;
         // Retry once on failure
-        match conn.lpush(queue, &serialized).await {
+        match conn.lpush(queue, serialized).await {
             Ok(()) => Ok(()),
             Err(e) => {
                 tracing::warn!("Redis push failed, retrying: {}", e);
                 tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-                conn.lpush(queue, &serialized).await
+                conn.lpush(queue, serialized).await
             }
         }
     }
@@ -63,12 +100,62 @@ impl RedisManager {
         }
     }
 
+    /// Moves up to `max` messages from `queue` onto `processing`, waiting up
+    /// to a second for the first, and returns them oldest first. They stay on
+    /// `processing` until `ack`, so a consumer that dies mid-batch finds them
+    /// again with `claimed`. Messages that fail to deserialize come back in
+    /// `unreadable`; `ack` clears them along with the rest.
+    pub async fn claim_batch<T: DeserializeOwned>(
+        &self,
+        queue: &str,
+        processing: &str,
+        max: usize,
+    ) -> Result<Claimed<T>, redis::RedisError> {
+        let mut conn = self.get_conn().await?;
+        let first: Option<String> = conn.brpoplpush(queue, processing, 1.0).await?;
+        let Some(first) = first else {
+            return Ok(deserialize_all(Vec::new()));
+        };
+        let mut serialized = vec![first];
+        if max > 1 {
+            let mut pipe = redis::pipe();
+            for _ in 1..max {
+                pipe.rpoplpush(queue, processing);
+            }
+            let more: Vec<Option<String>> = pipe.query_async(&mut conn).await?;
+            serialized.extend(more.into_iter().flatten());
+        }
+        Ok(deserialize_all(serialized))
+    }
+
+    /// Messages claimed onto `processing` and not yet acknowledged, oldest
+    /// first.
+    pub async fn claimed<T: DeserializeOwned>(
+        &self,
+        processing: &str,
+    ) -> Result<Claimed<T>, redis::RedisError> {
+        let mut conn = self.get_conn().await?;
+        let mut serialized: Vec<String> = conn.lrange(processing, 0, -1).await?;
+        serialized.reverse();
+        Ok(deserialize_all(serialized))
+    }
+
+    /// Acknowledges everything claimed onto `processing`.
+    pub async fn ack(&self, processing: &str) -> Result<(), redis::RedisError> {
+        let mut conn = self.get_conn().await?;
+        conn.del(processing).await
+    }
+
     pub async fn publish_message<T: Serialize>(
         &self,
         channel: &str,
         message: &T,
     ) -> Result<(), redis::RedisError> {
         let serialized = serde_json::to_string(message).expect("Failed to serialize message");
+        #[cfg(test)]
+        if self.offline {
+            return Ok(());
+        }
         let mut conn = self.get_conn().await?;
         match conn.publish(channel, &serialized).await {
             Ok(()) => Ok(()),
@@ -85,4 +172,41 @@ impl RedisManager {
         pubsub.subscribe(channel).await?;
         Ok(pubsub)
     }
+
+    /// Subscribes to every channel matching `pattern`.
+    pub async fn psubscribe(&self, pattern: &str) -> Result<PubSub, redis::RedisError> {
+        let mut pubsub = self.client.get_async_pubsub().await?;
+        pubsub.psubscribe(pattern).await?;
+        Ok(pubsub)
+    }
+}
+
+fn deserialize_all<T: DeserializeOwned>(serialized: Vec<String>) -> Claimed<T> {
+    let mut claimed = Claimed {
+        messages: Vec::new(),
+        unreadable: Vec::new(),
+    };
+    for value in serialized {
+        match serde_json::from_str(&value) {
+            Ok(message) => claimed.messages.push(message),
+            Err(e) => {
+                tracing::error!("Failed to deserialized message: {}", e);
+                claimed.unreadable.push(value);
+            }
+        }
+    }
+    claimed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreadable_payloads_are_handed_back_as_stored() {
+        let claimed: Claimed<u32> =
+            deserialize_all(vec!["1".to_string(), "{oops".to_string(), "2".to_string()]);
+        assert_eq!(claimed.messages, vec![1, 2]);
+        assert_eq!(claimed.unreadable, vec!["{oops".to_string()]);
+    }
 }
@@ -0,0 +1,44 @@
+use crate::types::order::OptionType;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum TransferKind {
+    Deposit,
+    Withdrawal,
+}
+
+/// A deposit or withdrawal, with the balance it left behind.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Transfer {
+    pub id: u64,
+    pub user_id: u32,
+    pub kind: TransferKind,
+    pub amount: f64,
+    pub available: f64,
+    pub locked: f64,
+    pub timestamp: u64,
+}
+
+/// A user's holding in one side of a book. `mark_price` is the book's mid,
+/// else its last trade, else the AMM price; without one the position is
+/// carried at zero value and zero unrealized PnL.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Position {
+    pub market_id: String,
+    pub option: OptionType,
+    pub quantity: i64,
+    pub avg_cost: f64,
+    pub realized_pnl: f64,
+    pub unrealized_pnl: f64,
+    pub mark_price: Option<f64>,
+    pub value: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Balance {
+    pub user_id: u32,
+    pub available: f64,
+    pub locked: f64,
+    /// Open positions only.
+    pub positions: Vec<Position>,
+}
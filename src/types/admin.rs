@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+/// Operator commands acting on the engine as a whole.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum AdminAction {
+    /// Reject every new order and stake from the user; with `cancel_orders`
+    /// their resting orders are pulled and the funds released.
+    BlockUser {
+        user_id: u32,
+        cancel_orders: bool,
+    },
+    UnblockUser {
+        user_id: u32,
+    },
+    /// Reject all new orders and stakes on every market.
+    HaltAll {
+        cancel_orders: bool,
+    },
+    ResumeAll,
+    /// Reopen a market halted by a circuit breaker or by reconciliation.
+    ResumeMarket {
+        market_id: String,
+    },
+}
+
+/// One applied admin command, kept for the audit trail.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: u64,
+    pub timestamp: u64,
+    pub operator: String,
+    pub reason: String,
+    pub action: AdminAction,
+    pub cancelled_orders: usize,
+    /// Why the order sweep stopped short; the rest of the action applied.
+    #[serde(default)]
+    pub error: Option<String>,
+}
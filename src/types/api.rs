@@ -1,4 +1,14 @@
-use crate::types::order::{OptionType, Order, OrderType, Trade};
+use crate::types::{
+    account::{Balance, Position, Transfer},
+    admin::{AdminAction, AuditEntry},
+    ledger::LedgerAudit,
+    market::{Market, MarketKind, MarketMetadata, Resolution, ScalarRange, TradingConfig},
+    market_data::{
+        AmmSnapshot, Candle, CandleInterval, CombinedBook, L3BookSnapshot, PoolSnapshot, Ticker,
+    },
+    order::{OptionType, Order, OrderType, Trade},
+    risk::{RejectCode, RiskLimits, RiskScope},
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -32,10 +42,128 @@ pub enum MessageFromApi {
     CreateMarket {
         market_id: String,
         question: String,
+        kind: MarketKind,
+        outcomes: Vec<String>,
+        range: Option<ScalarRange>,
+        metadata: Box<MarketMetadata>,
+        trading: TradingConfig,
+        client_id: String,
+    },
+    GetMarkets {
+        client_id: String,
+    },
+    SetRiskLimits {
+        scope: RiskScope,
+        limits: RiskLimits,
+        client_id: String,
+    },
+    Admin {
+        action: AdminAction,
+        operator: String,
+        reason: String,
+        client_id: String,
+    },
+    GetAuditLog {
+        client_id: String,
+    },
+    GetLedger {
+        user_id: u32,
+        client_id: String,
+    },
+    GetBalance {
+        user_id: u32,
+        client_id: String,
+    },
+    GetPositions {
+        user_id: u32,
+        client_id: String,
+    },
+    CreateAccount {
+        user_id: u32,
+        client_id: String,
+    },
+    Deposit {
+        user_id: u32,
+        amount: f64,
+        client_id: String,
+    },
+    Withdraw {
+        user_id: u32,
+        amount: f64,
+        client_id: String,
+    },
+    GetL3Snapshot {
+        market_id: String,
+        client_id: String,
+    },
+    GetCandles {
+        market_id: String,
+        option: OptionType,
+        interval: CandleInterval,
+        limit: usize,
+        client_id: String,
+    },
+    GetTicker {
+        market_id: Option<String>,
+        client_id: String,
+    },
+    GetCombinedDepth {
+        market_id: String,
+        client_id: String,
+    },
+    GetAmm {
+        market_id: String,
+        client_id: String,
+    },
+    PlaceStake {
+        user_id: u32,
+        market_id: String,
+        option: OptionType,
+        amount: f64,
+        client_id: String,
+    },
+    GetPool {
+        market_id: String,
+        client_id: String,
+    },
+    ResolveMarket {
+        market_id: String,
+        resolution: Resolution,
         client_id: String,
     },
 }
 
+impl MessageFromApi {
+    /// The id the reply to this request has to carry.
+    pub fn client_id(&self) -> &str {
+        match self {
+            MessageFromApi::CreateOrder { client_id, .. }
+            | MessageFromApi::CancelOrder { client_id, .. }
+            | MessageFromApi::GetOpenOrders { client_id, .. }
+            | MessageFromApi::GetDepth { client_id, .. }
+            | MessageFromApi::CreateMarket { client_id, .. }
+            | MessageFromApi::GetMarkets { client_id, .. }
+            | MessageFromApi::SetRiskLimits { client_id, .. }
+            | MessageFromApi::Admin { client_id, .. }
+            | MessageFromApi::GetAuditLog { client_id, .. }
+            | MessageFromApi::GetLedger { client_id, .. }
+            | MessageFromApi::GetBalance { client_id, .. }
+            | MessageFromApi::GetPositions { client_id, .. }
+            | MessageFromApi::CreateAccount { client_id, .. }
+            | MessageFromApi::Deposit { client_id, .. }
+            | MessageFromApi::Withdraw { client_id, .. }
+            | MessageFromApi::GetL3Snapshot { client_id, .. }
+            | MessageFromApi::GetCandles { client_id, .. }
+            | MessageFromApi::GetTicker { client_id, .. }
+            | MessageFromApi::GetCombinedDepth { client_id, .. }
+            | MessageFromApi::GetAmm { client_id, .. }
+            | MessageFromApi::PlaceStake { client_id, .. }
+            | MessageFromApi::GetPool { client_id, .. }
+            | MessageFromApi::ResolveMarket { client_id, .. } => client_id,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum MessageToApi {
     OrderPlaced {
@@ -67,8 +195,128 @@ pub enum MessageToApi {
         market_id: String,
         client_id: String,
     },
+    L3Snapshot {
+        market_id: String,
+        yes: L3BookSnapshot,
+        no: L3BookSnapshot,
+        client_id: String,
+    },
+    Candles {
+        market_id: String,
+        option: OptionType,
+        interval: CandleInterval,
+        candles: Vec<Candle>,
+        client_id: String,
+    },
+    Tickers {
+        tickers: Vec<Ticker>,
+        client_id: String,
+    },
+    CombinedDepth {
+        book: CombinedBook,
+        client_id: String,
+    },
+    Amm {
+        amm: AmmSnapshot,
+        client_id: String,
+    },
+    StakePlaced {
+        pool: PoolSnapshot,
+        client_id: String,
+    },
+    Pool {
+        pool: PoolSnapshot,
+        client_id: String,
+    },
+    MarketResolved {
+        market_id: String,
+        resolution: Resolution,
+        client_id: String,
+    },
+    Markets {
+        markets: Vec<Market>,
+        client_id: String,
+    },
+    OrderRejected {
+        code: RejectCode,
+        message: String,
+        client_id: String,
+    },
+    RiskLimitsSet {
+        scope: RiskScope,
+        limits: RiskLimits,
+        client_id: String,
+    },
+    AdminApplied {
+        entry: AuditEntry,
+        client_id: String,
+    },
+    AuditLog {
+        entries: Vec<AuditEntry>,
+        client_id: String,
+    },
+    AccountCreated {
+        user_id: u32,
+        client_id: String,
+    },
+    TransferCompleted {
+        transfer: Transfer,
+        client_id: String,
+    },
+    Ledger {
+        audit: LedgerAudit,
+        client_id: String,
+    },
+    Balance {
+        balance: Balance,
+        client_id: String,
+    },
+    Positions {
+        positions: Vec<Position>,
+        client_id: String,
+    },
+    /// Pushed to the owner's private stream whenever their balance moves;
+    /// not a reply to any request. The stream is keyed by the `user_id` the
+    /// subscriber names and is not authenticated yet.
+    BalanceUpdated {
+        balance: Balance,
+    },
     Error {
         message: String,
         client_id: String,
     },
 }
+
+impl MessageToApi {
+    /// The id of the request this answers; `None` for unsolicited pushes.
+    pub fn client_id(&self) -> Option<&str> {
+        match self {
+            MessageToApi::OrderPlaced { client_id, .. }
+            | MessageToApi::OrderMatched { client_id, .. }
+            | MessageToApi::OrderCancelled { client_id, .. }
+            | MessageToApi::OpenOrders { client_id, .. }
+            | MessageToApi::Depth { client_id, .. }
+            | MessageToApi::MarketCreated { client_id, .. }
+            | MessageToApi::L3Snapshot { client_id, .. }
+            | MessageToApi::Candles { client_id, .. }
+            | MessageToApi::Tickers { client_id, .. }
+            | MessageToApi::CombinedDepth { client_id, .. }
+            | MessageToApi::Amm { client_id, .. }
+            | MessageToApi::StakePlaced { client_id, .. }
+            | MessageToApi::Pool { client_id, .. }
+            | MessageToApi::MarketResolved { client_id, .. }
+            | MessageToApi::Markets { client_id, .. }
+            | MessageToApi::OrderRejected { client_id, .. }
+            | MessageToApi::RiskLimitsSet { client_id, .. }
+            | MessageToApi::AdminApplied { client_id, .. }
+            | MessageToApi::AuditLog { client_id, .. }
+            | MessageToApi::AccountCreated { client_id, .. }
+            | MessageToApi::TransferCompleted { client_id, .. }
+            | MessageToApi::Ledger { client_id, .. }
+            | MessageToApi::Balance { client_id, .. }
+            | MessageToApi::Positions { client_id, .. }
+            | MessageToApi::Error { client_id, .. } => Some(client_id),
+            MessageToApi::BalanceUpdated { .. } => None,
+        }
+    }
+}
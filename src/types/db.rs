@@ -1,13 +1,126 @@
 use crate::types::{
+    account::Transfer,
+    admin::AuditEntry,
+    ledger::{JournalEntry, LedgerAccount},
     market::Market,
-    order::{Order, Trade},
+    order::{OptionType, Order, Trade},
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum DbMessage {
     SaveOrder(Order),
     SaveTrade(Trade),
     SaveMarket(Market),
     UpdateBalance { user_id: u32, balance: f64 },
+    SaveAudit(AuditEntry),
+    SaveTransfer(Transfer),
+    SaveJournal(JournalEntry),
+}
+
+/// A `DbMessage` as it travels on `db_queue`. The id is unique per write and
+/// recorded with it, so redelivering a write never applies it twice.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct DbWrite {
+    pub id: String,
+    pub message: DbMessage,
+}
+
+impl DbWrite {
+    pub fn new(message: DbMessage) -> Self {
+        DbWrite {
+            id: uuid::Uuid::new_v4().to_string(),
+            message,
+        }
+    }
+}
+
+/// Highest id of each kind the store holds, so a restarted engine carries on
+/// after them rather than handing the same ids out again.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct LastIds {
+    pub order: u64,
+    pub transfer: u64,
+    pub audit: u64,
+    pub journal: u64,
+}
+
+/// What a restarted engine takes its accounts back from.
+#[derive(Clone, Debug, Default)]
+pub struct StoredAccounts {
+    /// Every account ever opened.
+    pub user_ids: Vec<u32>,
+    /// Each ledger account's total over the stored journal.
+    pub ledger: HashMap<LedgerAccount, f64>,
+    /// Orders that were still resting when the engine stopped.
+    pub open_orders: Vec<Order>,
+}
+
+/// Narrows a history query; every field is optional. `from` / `to` are
+/// inclusive unix-second bounds on the timestamp.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HistoryFilter {
+    pub user_id: Option<u32>,
+    pub market_id: Option<String>,
+    pub option: Option<OptionType>,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum DbQuery {
+    OrderHistory(HistoryFilter),
+    TradeHistory(HistoryFilter),
+    /// Latest public trades of a market, newest first.
+    RecentTrades {
+        market_id: String,
+        limit: usize,
+    },
+}
+
+/// A query pushed onto `db_queries`; the answer is published on
+/// `db_responses` tagged with the same `client_id`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DbRequest {
+    pub query: DbQuery,
+    pub client_id: String,
+}
+
+/// One page of results, newest first, and how many matched in total.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum DbResponse {
+    Orders {
+        page: Page<Order>,
+        client_id: String,
+    },
+    Trades {
+        page: Page<Trade>,
+        client_id: String,
+    },
+    Error {
+        message: String,
+        client_id: String,
+    },
+}
+
+impl DbResponse {
+    pub fn client_id(&self) -> &str {
+        match self {
+            DbResponse::Orders { client_id, .. }
+            | DbResponse::Trades { client_id, .. }
+            | DbResponse::Error { client_id, .. } => client_id,
+        }
+    }
 }
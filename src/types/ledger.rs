@@ -0,0 +1,98 @@
+use crate::types::order::Trade;
+use serde::{Deserialize, Serialize};
+
+/// Where money sits. Every journal entry moves it between these and nets to
+/// zero, so the platform-wide sum of all accounts is always zero.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum LedgerAccount {
+    Available(u32),
+    Locked(u32),
+    /// Counterpart of trade cash, AMM fills and settlement payouts; its
+    /// balance is the collateral held against open positions.
+    Clearing,
+    /// Commission taken by the platform.
+    Fees,
+    /// The platform's side of AMM fills: it puts up the rest of a share's
+    /// collateral for every share its AMMs sell and is paid for the opposite
+    /// shares it keeps.
+    House,
+    /// Money outside the platform, moved by deposits and withdrawals.
+    External,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum EntryReason {
+    Deposit,
+    Withdrawal,
+    Lock,
+    Unlock,
+    TradeDebit,
+    TradeCredit,
+    Fee,
+    Payout,
+}
+
+/// What caused an entry.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum LedgerRef {
+    Order(u64),
+    Trade {
+        buy_order_id: u64,
+        sell_order_id: u64,
+    },
+    Transfer(u64),
+    Market(String),
+    /// Funds an earlier engine released on restart for stakes in markets
+    /// that did not come back with it. Restarts now refuse instead, but
+    /// stored journals still carry it.
+    Restart,
+}
+
+impl LedgerRef {
+    pub fn trade(trade: &Trade) -> Self {
+        LedgerRef::Trade {
+            buy_order_id: trade.buy_order_id,
+            sell_order_id: trade.sell_order_id,
+        }
+    }
+}
+
+/// A signed change to one account; positive adds to its balance.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Posting {
+    pub account: LedgerAccount,
+    pub amount: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: u64,
+    pub timestamp: u64,
+    pub reason: EntryReason,
+    pub reference: LedgerRef,
+    pub postings: Vec<Posting>,
+}
+
+impl JournalEntry {
+    pub fn touches(&self, user_id: u32) -> bool {
+        self.postings.iter().any(|p| {
+            matches!(
+                p.account,
+                LedgerAccount::Available(id) | LedgerAccount::Locked(id) if id == user_id
+            )
+        })
+    }
+}
+
+/// A user's balance by the ledger next to the live figure.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LedgerAudit {
+    pub user_id: u32,
+    /// The user's latest entries; the full history is in the stored journal.
+    pub entries: Vec<JournalEntry>,
+    /// `(available, locked)` summed from every posting to the user's accounts.
+    pub rebuilt: (f64, f64),
+    /// `(available, locked)` as the balance manager holds it.
+    pub balance: (f64, f64),
+    pub consistent: bool,
+}
@@ -1,14 +1,195 @@
+use crate::types::order::OptionType;
 use serde::{Deserialize, Serialize};
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum MarketKind {
+    #[default]
+    OrderBook,
+    Parimutuel,
+    /// N mutually exclusive outcomes, each traded as its own Yes/No book
+    /// under `outcome_market_id`. Exactly one outcome wins.
+    MultiOutcome,
+    /// Payout scales with a numeric result inside a `ScalarRange`. The Yes
+    /// book trades the Long token and the No book the Short token.
+    Scalar,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum MarketStatus {
+    #[default]
+    Open,
+    /// Orders rest without matching until the auction uncrosses.
+    Auction,
+    /// A circuit breaker stopped trading for its cooldown.
+    Halted,
+    /// Trading has stopped; the market is waiting to be resolved.
+    Closed,
+    Resolved,
+}
+
+/// How a market settled: the winning side of a binary market, the winning
+/// label of a multi-outcome market or the observed value of a scalar market.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Resolution {
+    Binary(OptionType),
+    Outcome(String),
+    Scalar(f64),
+}
+
+/// Bounds of a scalar market. Long pays in full at or above `upper`, nothing
+/// at or below `lower` and linearly in between; Short pays the remainder, so
+/// a Long and a Short together still pay out one full share.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ScalarRange {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl ScalarRange {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.lower.is_finite() || !self.upper.is_finite() || self.lower >= self.upper {
+            return Err("Scalar range needs finite bounds with lower < upper".to_string());
+        }
+        Ok(())
+    }
+
+    /// Fraction of a full share that one Long token pays when the market
+    /// resolves at `value`.
+    pub fn long_fraction(&self, value: f64) -> f64 {
+        ((value - self.lower) / (self.upper - self.lower)).clamp(0.0, 1.0)
+    }
+}
+
+/// Id of the Yes/No book pair that trades one outcome of a multi-outcome
+/// market.
+pub fn outcome_market_id(market_id: &str, outcome: &str) -> String {
+    format!("{}:{}", market_id, outcome)
+}
+
+/// Descriptive fields set at creation. Times are unix seconds.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MarketMetadata {
+    pub category: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    pub resolution_source: Option<String>,
+    /// When trading stops.
+    pub close_time: Option<u64>,
+    pub expected_resolution_time: Option<u64>,
+    pub image_url: Option<String>,
+}
+
+impl MarketMetadata {
+    pub fn validate(&self, created_at: u64) -> Result<(), String> {
+        if let Some(close_time) = self.close_time
+            && close_time <= created_at
+        {
+            return Err("Close time must be in the future".to_string());
+        }
+        if let (Some(close_time), Some(resolution_time)) =
+            (self.close_time, self.expected_resolution_time)
+            && resolution_time < close_time
+        {
+            return Err("Expected resolution time is before the close time".to_string());
+        }
+        if self.tags.iter().any(|tag| tag.trim().is_empty()) {
+            return Err("Tags must be non-empty".to_string());
+        }
+        if let Some(url) = &self.image_url
+            && !(url.starts_with("https://") || url.starts_with("http://"))
+        {
+            return Err("Image URL must be http(s)".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum BreakerAction {
+    /// Reject orders for the cooldown, then trade continuously again.
+    Halt,
+    /// Collect orders in a call auction that uncrosses after the cooldown.
+    Auction,
+}
+
+/// Trips when the traded price moves more than `max_move` within
+/// `window_secs`. Prices are compared on the Yes side.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// Off, every fill goes through and nothing trips.
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    pub max_move: f64,
+    pub window_secs: u64,
+    pub cooldown_secs: u64,
+    pub action: BreakerAction,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            enabled: true,
+            max_move: 2.0,
+            window_secs: 60,
+            cooldown_secs: 60,
+            action: BreakerAction::Auction,
+        }
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl CircuitBreakerConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.max_move.is_finite() || self.max_move <= 0.0 {
+            return Err("Circuit breaker move must be positive".to_string());
+        }
+        if self.window_secs == 0 || self.cooldown_secs == 0 {
+            return Err("Circuit breaker window and cooldown must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// How the engine runs a market, chosen at creation.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TradingConfig {
+    /// LMSR liquidity `b`; attaches an AMM to every book when set.
+    pub amm_liquidity: Option<f64>,
+    /// Open with a call auction lasting this long instead of trading at once.
+    pub opening_auction_secs: Option<u64>,
+    /// Defaults to `CircuitBreakerConfig::default()`.
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Market {
     pub market_id: String,
     pub question: String,
+    pub kind: MarketKind,
+    #[serde(default)]
+    pub outcomes: Vec<String>,
+    #[serde(default)]
+    pub range: Option<ScalarRange>,
+    #[serde(flatten)]
+    pub metadata: MarketMetadata,
+    #[serde(default)]
+    pub status: MarketStatus,
     pub created_at: u64,
 }
 
 impl Market {
-    pub fn new(market_id: String, question: String) -> Self {
+    pub fn new(
+        market_id: String,
+        question: String,
+        kind: MarketKind,
+        outcomes: Vec<String>,
+        range: Option<ScalarRange>,
+        metadata: MarketMetadata,
+    ) -> Self {
         let created_at = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
@@ -16,7 +197,25 @@ impl Market {
         Market {
             market_id,
             question,
+            kind,
+            outcomes,
+            range,
+            metadata,
+            status: MarketStatus::Open,
             created_at,
         }
     }
+
+    /// Ids of the Yes/No book pairs the market trades in; none for a pool.
+    pub fn book_ids(&self) -> Vec<String> {
+        match self.kind {
+            MarketKind::MultiOutcome => self
+                .outcomes
+                .iter()
+                .map(|outcome| outcome_market_id(&self.market_id, outcome))
+                .collect(),
+            MarketKind::Parimutuel => Vec::new(),
+            MarketKind::OrderBook | MarketKind::Scalar => vec![self.market_id.clone()],
+        }
+    }
 }
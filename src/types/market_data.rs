@@ -0,0 +1,160 @@
+use crate::types::order::{OptionType, OrderType};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum L3EventKind {
+    Add,
+    Modify,
+    Fill,
+    Cancel,
+}
+
+/// A single order-level change to a book. `order_ref` is an anonymized
+/// stand-in for the order id, stable for the lifetime of the order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct L3Event {
+    pub sequence: u64,
+    pub kind: L3EventKind,
+    pub order_ref: u64,
+    pub option: OptionType,
+    pub side: OrderType,
+    pub price: f64,
+    pub quantity: u32,
+    pub timestamp: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct L3Order {
+    pub order_ref: u64,
+    pub side: OrderType,
+    pub price: f64,
+    pub quantity: u32,
+}
+
+/// Every resting order of one book in queue order. `sequence` is the
+/// sequence of the last `L3Event` already reflected in the snapshot.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct L3BookSnapshot {
+    pub option: OptionType,
+    pub sequence: u64,
+    pub bids: Vec<L3Order>,
+    pub asks: Vec<L3Order>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum CandleInterval {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+impl CandleInterval {
+    pub const ALL: [CandleInterval; 4] = [
+        CandleInterval::OneMinute,
+        CandleInterval::FiveMinutes,
+        CandleInterval::OneHour,
+        CandleInterval::OneDay,
+    ];
+
+    pub fn seconds(&self) -> u64 {
+        match self {
+            CandleInterval::OneMinute => 60,
+            CandleInterval::FiveMinutes => 5 * 60,
+            CandleInterval::OneHour => 60 * 60,
+            CandleInterval::OneDay => 24 * 60 * 60,
+        }
+    }
+
+    /// Start of the bar that contains `timestamp` (both in unix seconds).
+    pub fn open_time(&self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.seconds()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Candle {
+    pub market_id: String,
+    pub option: OptionType,
+    pub interval: CandleInterval,
+    pub open_time: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: u64,
+    pub trade_count: u32,
+}
+
+/// Rolling 24h statistics for one side of a market. Prices are `None` until
+/// the first trade or quote is seen.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct OptionTicker {
+    pub last_price: Option<f64>,
+    pub best_bid: Option<f64>,
+    pub best_ask: Option<f64>,
+    pub high_24h: Option<f64>,
+    pub low_24h: Option<f64>,
+    pub price_change_24h: Option<f64>,
+    pub volume_24h: u64,
+    pub trade_count_24h: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Ticker {
+    pub market_id: String,
+    pub yes: OptionTicker,
+    pub no: OptionTicker,
+    pub updated_at: u64,
+}
+
+/// One Yes-price level of the combined book. `implied_quantity` comes from the
+/// No book: a No ask at `q` is a Yes bid at `10 - q`, a No bid at `q` is a Yes
+/// ask at `10 - q`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CombinedLevel {
+    pub price: f64,
+    pub probability: f64,
+    pub native_quantity: u32,
+    pub implied_quantity: u32,
+    pub total_quantity: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CombinedBook {
+    pub market_id: String,
+    pub bids: Vec<CombinedLevel>,
+    pub asks: Vec<CombinedLevel>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AmmSnapshot {
+    pub market_id: String,
+    pub b: f64,
+    pub q_yes: f64,
+    pub q_no: f64,
+    pub yes_price: f64,
+    pub no_price: f64,
+    pub subsidy: f64,
+    pub cash: f64,
+    pub pnl: f64,
+}
+
+/// Live state of a parimutuel pool. Odds are the gross payout per unit staked
+/// after commission, `None` while a side is empty.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PoolSnapshot {
+    pub market_id: String,
+    pub yes_pool: f64,
+    pub no_pool: f64,
+    pub yes_probability: f64,
+    pub no_probability: f64,
+    pub yes_odds: Option<f64>,
+    pub no_odds: Option<f64>,
+    pub stakers: usize,
+    pub resolved: Option<OptionType>,
+}
@@ -1,5 +1,11 @@
+pub mod account;
+pub mod admin;
 pub mod api;
 pub mod db;
+pub mod ledger;
 pub mod market;
+pub mod market_data;
 pub mod order;
+pub mod reconciliation;
+pub mod risk;
 pub mod ws;
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub enum OptionType {
     Yes,
     No,
@@ -13,6 +13,16 @@ pub enum OrderType {
     Sell,
 }
 
+/// Where an order is in its life. Filled and cancelled orders are final.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum OrderStatus {
+    #[default]
+    Open,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Order {
     pub id: u64,
@@ -21,8 +31,16 @@ pub struct Order {
     pub option: OptionType,
     pub order_type: OrderType,
     pub price: f64,
+    /// Quantity still open; the original size is this plus `filled_quantity`.
     pub quantity: u32,
     pub timestamp: u64,
+    #[serde(default)]
+    pub status: OrderStatus,
+    #[serde(default)]
+    pub filled_quantity: u32,
+    /// Volume-weighted price of the fills so far, 0 before the first.
+    #[serde(default)]
+    pub avg_fill_price: f64,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -59,6 +77,32 @@ impl Order {
             price,
             quantity,
             timestamp,
+            status: OrderStatus::Open,
+            filled_quantity: 0,
+            avg_fill_price: 0.0,
         }
     }
+
+    /// Executes `quantity` of the open quantity at `price`.
+    pub fn fill(&mut self, quantity: u32, price: f64) {
+        let quantity = quantity.min(self.quantity);
+        if quantity == 0 {
+            return;
+        }
+        let filled = self.filled_quantity + quantity;
+        self.avg_fill_price = (self.avg_fill_price * self.filled_quantity as f64
+            + price * quantity as f64)
+            / filled as f64;
+        self.filled_quantity = filled;
+        self.quantity -= quantity;
+        self.status = if self.quantity == 0 {
+            OrderStatus::Filled
+        } else {
+            OrderStatus::PartiallyFilled
+        };
+    }
+
+    pub fn cancel(&mut self) {
+        self.status = OrderStatus::Cancelled;
+    }
 }
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+/// A broken collateral invariant found by the reconciliation job.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Discrepancy {
+    /// `locked` differs from what the user's resting buys and pool stakes
+    /// should be holding.
+    LockedFunds {
+        user_id: u32,
+        locked: f64,
+        expected: f64,
+    },
+    /// The live balance differs from the one replayed from the ledger.
+    Ledger {
+        user_id: u32,
+        balance: (f64, f64),
+        rebuilt: (f64, f64),
+    },
+    /// The account's available balance went below zero, so it owes the
+    /// difference until it is covered.
+    NegativeBalance { user_id: u32, available: f64 },
+    /// User balances, fees, clearing and the house don't add up to net
+    /// deposits.
+    CashNotConserved { imbalance: f64 },
+    /// Clearing holds something other than a full payout for every
+    /// outstanding share pair.
+    Clearing { clearing: f64, collateral: f64 },
+    /// Outstanding Yes and No shares of a book pair differ, so the shares
+    /// are not fully collateralised.
+    Positions {
+        market_id: String,
+        yes: i64,
+        no: i64,
+    },
+}
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+/// Pre-trade limits. Global, market and user limits all apply, so the
+/// strictest one set wins for every field; a limit unset everywhere is not
+/// enforced.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct RiskLimits {
+    pub max_order_quantity: Option<u32>,
+    /// Largest `price * quantity` of a single order.
+    pub max_notional: Option<f64>,
+    /// Resting orders per user across all markets.
+    pub max_open_orders: Option<usize>,
+    /// Largest net shares per market, a No share counting as a short Yes
+    /// share. Open orders in the same direction count as filled.
+    pub max_position: Option<i64>,
+    /// Furthest an order may be priced from the option's last trade.
+    pub price_collar: Option<f64>,
+}
+
+impl RiskLimits {
+    /// The tighter of the two for every field set in both, else whichever
+    /// one is set.
+    pub fn stricter(self, other: RiskLimits) -> RiskLimits {
+        fn tighter<T: PartialOrd>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(if b < a { b } else { a }),
+                (a, b) => a.or(b),
+            }
+        }
+        RiskLimits {
+            max_order_quantity: tighter(self.max_order_quantity, other.max_order_quantity),
+            max_notional: tighter(self.max_notional, other.max_notional),
+            max_open_orders: tighter(self.max_open_orders, other.max_open_orders),
+            max_position: tighter(self.max_position, other.max_position),
+            price_collar: tighter(self.price_collar, other.price_collar),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum RiskScope {
+    Global,
+    Market(String),
+    User(u32),
+}
+
+/// Why the risk layer refused an order.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RejectCode {
+    MaxOrderQuantity,
+    MaxNotional,
+    MaxOpenOrders,
+    MaxPosition,
+    PriceCollar,
+    UserBlocked,
+    TradingHalted,
+}
@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 
-use super::order::Trade;
+use super::{
+    market_data::{Candle, CombinedBook, L3Event, PoolSnapshot, Ticker},
+    order::Trade,
+};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum WsMessage {
@@ -17,4 +20,72 @@ pub enum WsMessage {
     Trade {
         trade: Trade,
     },
+    L3Update {
+        market_id: String,
+        events: Vec<L3Event>,
+    },
+    Candle {
+        candle: Candle,
+    },
+    Ticker {
+        ticker: Ticker,
+    },
+    CombinedDepth {
+        book: CombinedBook,
+    },
+    Pool {
+        pool: PoolSnapshot,
+    },
+    MarketStatus {
+        market_id: String,
+        status: super::market::MarketStatus,
+    },
+    MarketResolved {
+        market_id: String,
+        resolution: super::market::Resolution,
+    },
+    ReconciliationAlert {
+        discrepancies: Vec<super::reconciliation::Discrepancy>,
+        halted: Vec<String>,
+    },
+}
+
+impl WsMessage {
+    /// The channel clients subscribe to for this message: `<kind>.<market_id>`,
+    /// e.g. `l3.m1` or `trades.m1`, and `alerts` for reconciliation alerts.
+    pub fn channel(&self) -> String {
+        let (kind, market_id) = match self {
+            WsMessage::Price { market_id, .. } => ("price", market_id),
+            WsMessage::Depth { market_id, .. } => ("depth", market_id),
+            WsMessage::Trade { trade } => ("trades", &trade.market_id),
+            WsMessage::L3Update { market_id, .. } => ("l3", market_id),
+            WsMessage::Candle { candle } => ("candles", &candle.market_id),
+            WsMessage::Ticker { ticker } => ("ticker", &ticker.market_id),
+            WsMessage::CombinedDepth { book } => ("combined_depth", &book.market_id),
+            WsMessage::Pool { pool } => ("pool", &pool.market_id),
+            WsMessage::MarketStatus { market_id, .. } => ("status", market_id),
+            WsMessage::MarketResolved { market_id, .. } => ("resolution", market_id),
+            WsMessage::ReconciliationAlert { .. } => return "alerts".to_string(),
+        };
+        format!("{}.{}", kind, market_id)
+    }
+}
+
+/// Sent by a client to pick the channels its socket receives.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum WsRequest {
+    Subscribe { channels: Vec<String> },
+    Unsubscribe { channels: Vec<String> },
+}
+
+/// Answer to a `WsRequest`.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum WsReply {
+    /// Every channel the socket is subscribed to after the request.
+    Subscriptions {
+        channels: Vec<String>,
+    },
+    Error {
+        message: String,
+    },
 }
@@ -1,8 +1,12 @@
-use crate::{redis::manager::RedisManager, types::ws::WsMessage};
+use crate::{
+    redis::manager::RedisManager,
+    types::ws::{WsMessage, WsReply, WsRequest},
+};
 use actix::{Actor, AsyncContext, Handler, Message, spawn};
 use actix_web::{App, HttpResponse, HttpServer, web};
 use actix_web_actors::ws;
 use futures_util::StreamExt;
+use std::collections::BTreeSet;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -20,18 +24,40 @@ pub async fn run_ws_server(redis: RedisManager, addr: &str) -> std::io::Result<(
 
 struct WsActor {
     redis: RedisManager,
+    // Channels this socket asked for; it receives nothing else
+    channels: BTreeSet<String>,
 }
 
-// Message to send within actor system
+impl WsActor {
+    fn apply(&mut self, request: WsRequest) -> WsReply {
+        match request {
+            WsRequest::Subscribe { channels } => self.channels.extend(channels),
+            WsRequest::Unsubscribe { channels } => {
+                for channel in &channels {
+                    self.channels.remove(channel);
+                }
+            }
+        }
+        WsReply::Subscriptions {
+            channels: self.channels.iter().cloned().collect(),
+        }
+    }
+}
+
+// Market update to send within actor system
 #[derive(Message)]
 #[rtype(result = "()")]
-struct TextMessage(String);
+struct Update(WsMessage);
 
-impl Handler<TextMessage> for WsActor {
+impl Handler<Update> for WsActor {
     type Result = ();
 
-    fn handle(&mut self, msg: TextMessage, ctx: &mut Self::Context) {
-        ctx.text(msg.0);
+    fn handle(&mut self, msg: Update, ctx: &mut Self::Context) {
+        if self.channels.contains(&msg.0.channel())
+            && let Ok(json) = serde_json::to_string(&msg.0)
+        {
+            ctx.text(json);
+        }
     }
 }
 
@@ -42,16 +68,16 @@ impl Actor for WsActor {
         let redis = self.redis.clone();
         let addr = ctx.address();
 
-        // Spawn a future to listen for Redis messages
+        // Spawn a future to listen for Redis messages. Trades come on each
+        // market's own `market_updates_<id>` channel, the rest on
+        // `market_updates`; the actor forwards only what the client subscribed to
         spawn(async move {
-            if let Ok(mut pubsub) = redis.subscribe("market_updates").await {
+            if let Ok(mut pubsub) = redis.psubscribe("market_updates*").await {
                 while let Some(msg) = pubsub.on_message().next().await {
-                    if let Ok(payload) = msg.get_payload::<String>() {
-                        if let Ok(message) = serde_json::from_str::<WsMessage>(&payload) {
-                            if let Ok(json) = serde_json::to_string(&message) {
-                                let _ = addr.do_send(TextMessage(json));
-                            }
-                        }
+                    if let Ok(payload) = msg.get_payload::<String>()
+                        && let Ok(message) = serde_json::from_str::<WsMessage>(&payload)
+                    {
+                        addr.do_send(Update(message));
                     }
                 }
             }
@@ -68,7 +94,17 @@ impl actix::StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsActor {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         match msg {
             Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
-            Ok(ws::Message::Text(text)) => ctx.text(text),
+            Ok(ws::Message::Text(text)) => {
+                let reply = match serde_json::from_str::<WsRequest>(&text) {
+                    Ok(request) => self.apply(request),
+                    Err(e) => WsReply::Error {
+                        message: format!("Invalid request: {}", e),
+                    },
+                };
+                if let Ok(json) = serde_json::to_string(&reply) {
+                    ctx.text(json);
+                }
+            }
             Ok(ws::Message::Close(reason)) => ctx.close(reason),
             _ => (),
         }
@@ -83,5 +119,42 @@ async fn ws_index(
     // Extract the RedisManager from the Data<Arc<RedisManager>>
     let redis = state.get_ref().as_ref().clone();
 
-    ws::start(WsActor { redis }, &req, stream)
+    ws::start(
+        WsActor {
+            redis,
+            channels: BTreeSet::new(),
+        },
+        &req,
+        stream,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sockets_receive_only_the_channels_they_picked() {
+        let mut actor = WsActor {
+            redis: RedisManager::offline(),
+            channels: BTreeSet::new(),
+        };
+        let request = r#"{"Subscribe":{"channels":["l3.m1","trades.m1"]}}"#;
+        actor.apply(serde_json::from_str(request).unwrap());
+        let reply = actor.apply(WsRequest::Unsubscribe {
+            channels: vec!["trades.m1".to_string()],
+        });
+        assert!(matches!(reply, WsReply::Subscriptions { channels } if channels == ["l3.m1"]));
+
+        let update = WsMessage::L3Update {
+            market_id: "m1".to_string(),
+            events: Vec::new(),
+        };
+        assert!(actor.channels.contains(&update.channel()));
+        let other = WsMessage::L3Update {
+            market_id: "m2".to_string(),
+            events: Vec::new(),
+        };
+        assert!(!actor.channels.contains(&other.channel()));
+    }
 }